@@ -0,0 +1,238 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{AUDIT_FILE_NAME, AUDIT_MAX_BYTES, AUDIT_RETAINED_FILES},
+    settings::settings_path,
+};
+
+/// Something worth keeping in the audit trail. Failed attempts only carry the
+/// input length; the typed text is never recorded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    LockStarted,
+    FailedAttempt {
+        input_length: usize,
+    },
+    Unlocked,
+    RecoveryCodeUsed {
+        remaining: usize,
+    },
+    DuressUnlock,
+    /// A duress action could not run; recorded here rather than shown.
+    DuressActionFailed {
+        reason: String,
+    },
+    GuestUnlock {
+        label: String,
+    },
+    TokenUnlock {
+        nonce: String,
+    },
+    KeyfileUnlock {
+        path: String,
+    },
+    ChallengeUnlock,
+    SettingsChanged {
+        fields: Vec<String>,
+    },
+    /// The settings file changed while locked; `deferred` fields wait for the
+    /// next lock.
+    SettingsReloaded {
+        applied: Vec<String>,
+        deferred: Vec<String>,
+    },
+    SettingsReloadRejected {
+        reason: String,
+    },
+    /// State that must outlive the session, such as a spent recovery code or
+    /// token nonce, could not be written to the settings file.
+    SettingsSaveFailed {
+        reason: String,
+    },
+    SessionEnded {
+        duration_secs: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Append-only JSON Lines file. Once it grows past `max_bytes` it is renamed
+/// to `<name>.1`, older generations shift up, and anything beyond
+/// `retained_files` is deleted.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    retained_files: usize,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_bytes: u64, retained_files: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            retained_files,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, event: AuditEvent) -> io::Result<()> {
+        let record = AuditRecord {
+            timestamp: Local::now().to_rfc3339(),
+            event,
+        };
+        self.append_record(&record)
+    }
+
+    pub fn append_record(&self, record: &AuditRecord) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.rotate_if_needed()?;
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)
+    }
+
+    /// Every readable record, oldest rotated file first. Lines that do not
+    /// parse are skipped so a torn write cannot hide the rest of the log.
+    pub fn read_all(&self) -> io::Result<Vec<AuditRecord>> {
+        let mut records = Vec::new();
+        for generation in (0..=self.retained_files).rev() {
+            let path = self.generation_path(generation);
+            let file = match fs::File::open(&path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for line in BufReader::new(file).lines() {
+                if let Ok(record) = serde_json::from_str(&line?) {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    fn rotate_if_needed(&self) -> io::Result<()> {
+        let size = match fs::metadata(&self.path) {
+            Ok(meta) => meta.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if size < self.max_bytes {
+            return Ok(());
+        }
+        if self.retained_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let oldest = self.generation_path(self.retained_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for generation in (0..self.retained_files).rev() {
+            let from = self.generation_path(generation);
+            if from.exists() {
+                fs::rename(&from, self.generation_path(generation + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn generation_path(&self, generation: usize) -> PathBuf {
+        if generation == 0 {
+            return self.path.clone();
+        }
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{generation}"));
+        self.path.with_file_name(name)
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new(audit_log_path(), AUDIT_MAX_BYTES, AUDIT_RETAINED_FILES)
+    }
+}
+
+pub fn audit_log_path() -> PathBuf {
+    settings_path().with_file_name(AUDIT_FILE_NAME)
+}
+
+/// Best-effort write to the default log; the lock screen must keep working
+/// even when the log cannot be written.
+pub fn record(event: AuditEvent) {
+    if let Err(err) = AuditLog::default().append(event) {
+        eprintln!("audit log write failed: {err}");
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditSummary {
+    pub locks: usize,
+    pub unlocks: usize,
+    pub recovery_unlocks: usize,
+    pub duress_unlocks: usize,
+    pub failed_duress_actions: usize,
+    pub guest_unlocks: usize,
+    pub token_unlocks: usize,
+    pub keyfile_unlocks: usize,
+    pub challenge_unlocks: usize,
+    pub failed_attempts: usize,
+    pub settings_changes: usize,
+    pub settings_reloads: usize,
+    pub rejected_reloads: usize,
+    pub failed_saves: usize,
+    pub total_locked_secs: u64,
+    pub first_timestamp: Option<String>,
+    pub last_timestamp: Option<String>,
+}
+
+pub fn summarize(records: &[AuditRecord]) -> AuditSummary {
+    let mut summary = AuditSummary {
+        first_timestamp: records.first().map(|r| r.timestamp.clone()),
+        last_timestamp: records.last().map(|r| r.timestamp.clone()),
+        ..AuditSummary::default()
+    };
+    for record in records {
+        match &record.event {
+            AuditEvent::LockStarted => summary.locks += 1,
+            AuditEvent::FailedAttempt { .. } => summary.failed_attempts += 1,
+            AuditEvent::Unlocked => summary.unlocks += 1,
+            AuditEvent::RecoveryCodeUsed { .. } => summary.recovery_unlocks += 1,
+            AuditEvent::DuressUnlock => summary.duress_unlocks += 1,
+            AuditEvent::DuressActionFailed { .. } => summary.failed_duress_actions += 1,
+            AuditEvent::GuestUnlock { .. } => summary.guest_unlocks += 1,
+            AuditEvent::TokenUnlock { .. } => summary.token_unlocks += 1,
+            AuditEvent::KeyfileUnlock { .. } => summary.keyfile_unlocks += 1,
+            AuditEvent::ChallengeUnlock => summary.challenge_unlocks += 1,
+            AuditEvent::SettingsChanged { .. } => summary.settings_changes += 1,
+            AuditEvent::SettingsReloaded { .. } => summary.settings_reloads += 1,
+            AuditEvent::SettingsReloadRejected { .. } => summary.rejected_reloads += 1,
+            AuditEvent::SettingsSaveFailed { .. } => summary.failed_saves += 1,
+            AuditEvent::SessionEnded { duration_secs } => {
+                summary.total_locked_secs += duration_secs
+            }
+        }
+    }
+    summary
+}
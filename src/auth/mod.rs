@@ -0,0 +1,237 @@
+//! Platform-independent unlock factors. Each configured factor is a stage in
+//! an [`AuthChain`]; the lock screen only feeds typed input into the chain and
+//! reacts to the returned [`AuthOutcome`].
+
+mod password;
+pub mod shares;
+pub mod totp;
+
+pub use password::{PasswordAuthenticator, PinAuthenticator};
+pub use shares::ShareAuthenticator;
+pub use totp::TotpAuthenticator;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{password::PasswordHash, secret::SecretString, settings::Settings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthOutcome {
+    Accept,
+    Reject,
+    NeedMoreInput,
+}
+
+pub trait Authenticator: Send {
+    /// Short label shown in front of the masked input, e.g. "Password".
+    fn prompt(&self) -> String;
+
+    fn verify(&mut self, input: &str) -> AuthOutcome;
+
+    /// Drops any partial progress so the factor starts over.
+    fn reset(&mut self) {}
+
+    /// Whether the last accepted input was a duress credential.
+    fn under_duress(&self) -> bool {
+        false
+    }
+
+    /// Copies state that has to outlive this lock screen, such as the last
+    /// TOTP step used, into `config` if it describes this factor. Returns
+    /// `true` when `config` changed.
+    fn record_use(&self, _config: &mut AuthenticatorConfig) -> bool {
+        false
+    }
+}
+
+/// Factors as they are persisted in `Settings::authenticators`, in the order
+/// they are asked for.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuthenticatorConfig {
+    /// Uses `Settings::password_hash`.
+    Password,
+    Pin {
+        hash: PasswordHash,
+    },
+    /// Base32 secret shared with the authenticator app.
+    Totp {
+        secret: SecretString,
+        #[serde(default = "default_totp_digits")]
+        digits: u32,
+        #[serde(default = "default_totp_period")]
+        period_secs: u64,
+        #[serde(default = "default_totp_skew")]
+        #[schemars(range(max = totp::MAX_TOTP_SKEW_STEPS))]
+        skew_steps: u64,
+        /// Time step of the last accepted code, so it cannot be replayed on
+        /// a later lock screen.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_used_step: Option<u64>,
+    },
+    /// Hash of the secret rebuilt from `threshold` Shamir shares.
+    Shares {
+        threshold: u8,
+        hash: PasswordHash,
+    },
+}
+
+impl AuthenticatorConfig {
+    pub fn build(&self, settings: &Settings) -> Box<dyn Authenticator> {
+        match self {
+            AuthenticatorConfig::Password => Box::new(PasswordAuthenticator::with_duress(
+                settings.password_verifier(),
+                settings.duress.as_ref().map(|duress| duress.hash.clone()),
+            )),
+            AuthenticatorConfig::Pin { hash } => Box::new(PinAuthenticator::new(hash.clone())),
+            AuthenticatorConfig::Totp {
+                secret,
+                digits,
+                period_secs,
+                skew_steps,
+                last_used_step,
+            } => match totp::base32_decode(secret.expose()).filter(|bytes| !bytes.is_empty()) {
+                Some(bytes) => Box::new(
+                    TotpAuthenticator::new(bytes, *digits, *period_secs, *skew_steps)
+                        .with_last_used_step(*last_used_step),
+                ),
+                None => Box::new(Unavailable("TOTP secret is invalid")),
+            },
+            AuthenticatorConfig::Shares { threshold, hash } => {
+                Box::new(ShareAuthenticator::new(*threshold, hash.clone()))
+            }
+        }
+    }
+}
+
+fn default_totp_digits() -> u32 {
+    totp::DEFAULT_TOTP_DIGITS
+}
+
+fn default_totp_period() -> u64 {
+    totp::DEFAULT_TOTP_PERIOD_SECS
+}
+
+fn default_totp_skew() -> u64 {
+    totp::DEFAULT_TOTP_SKEW_STEPS
+}
+
+/// Stand-in for a factor whose configuration cannot be used. It fails closed
+/// so a broken entry never lets the chain skip a stage.
+struct Unavailable(&'static str);
+
+impl Authenticator for Unavailable {
+    fn prompt(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn verify(&mut self, _input: &str) -> AuthOutcome {
+        AuthOutcome::Reject
+    }
+}
+
+/// Walks the configured factors in order. A rejection at any stage restarts
+/// the chain from the first factor.
+pub struct AuthChain {
+    stages: Vec<Box<dyn Authenticator>>,
+    current: usize,
+    unlocked_under_duress: bool,
+}
+
+impl AuthChain {
+    pub fn new(stages: Vec<Box<dyn Authenticator>>) -> Self {
+        Self {
+            stages,
+            current: 0,
+            unlocked_under_duress: false,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut configs = settings.authenticators.clone();
+        if configs.is_empty() {
+            configs.push(AuthenticatorConfig::Password);
+        }
+        Self::new(
+            configs
+                .iter()
+                .map(|config| config.build(settings))
+                .collect(),
+        )
+    }
+
+    pub fn submit(&mut self, input: &str) -> AuthOutcome {
+        let Some(stage) = self.stages.get_mut(self.current) else {
+            return AuthOutcome::Reject;
+        };
+        match stage.verify(input) {
+            AuthOutcome::Accept if self.current + 1 < self.stages.len() => {
+                self.current += 1;
+                AuthOutcome::NeedMoreInput
+            }
+            AuthOutcome::Accept => {
+                self.unlocked_under_duress = self.stages.iter().any(|stage| stage.under_duress());
+                self.reset();
+                AuthOutcome::Accept
+            }
+            AuthOutcome::NeedMoreInput => AuthOutcome::NeedMoreInput,
+            AuthOutcome::Reject => {
+                self.reset();
+                AuthOutcome::Reject
+            }
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        self.stages
+            .get(self.current)
+            .map(|stage| stage.prompt())
+            .unwrap_or_else(|| "Password".to_string())
+    }
+
+    pub fn stage_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Set by the accepting `submit` when any stage matched a duress
+    /// credential; the caller fires the duress actions silently.
+    pub fn unlocked_under_duress(&self) -> bool {
+        self.unlocked_under_duress
+    }
+
+    /// Writes what the stages have to remember into the matching entries of
+    /// `configs`; see `Authenticator::record_use`. Returns `true` when any
+    /// entry changed and should be saved.
+    pub fn record_use(&self, configs: &mut [AuthenticatorConfig]) -> bool {
+        let mut changed = false;
+        for config in configs {
+            for stage in &self.stages {
+                changed |= stage.record_use(config);
+            }
+        }
+        changed
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+        self.current = 0;
+    }
+}
+
+impl fmt::Debug for AuthChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prompts: Vec<String> = self.stages.iter().map(|stage| stage.prompt()).collect();
+        f.debug_struct("AuthChain")
+            .field("stages", &prompts)
+            .field("current", &self.current)
+            .field("unlocked_under_duress", &self.unlocked_under_duress)
+            .finish()
+    }
+}
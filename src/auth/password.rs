@@ -0,0 +1,81 @@
+use super::{AuthOutcome, Authenticator};
+use crate::password::PasswordHash;
+
+/// Checks the unlock password and, when configured, the duress password.
+/// Both hashes are always computed so the two paths take the same time.
+pub struct PasswordAuthenticator {
+    verifier: PasswordHash,
+    duress: Option<PasswordHash>,
+    duress_matched: bool,
+}
+
+impl PasswordAuthenticator {
+    pub fn new(verifier: PasswordHash) -> Self {
+        Self::with_duress(verifier, None)
+    }
+
+    pub fn with_duress(verifier: PasswordHash, duress: Option<PasswordHash>) -> Self {
+        Self {
+            verifier,
+            duress,
+            duress_matched: false,
+        }
+    }
+}
+
+impl Authenticator for PasswordAuthenticator {
+    fn prompt(&self) -> String {
+        "Password".to_string()
+    }
+
+    fn verify(&mut self, input: &str) -> AuthOutcome {
+        let genuine = self.verifier.verify(input);
+        let duress = self
+            .duress
+            .as_ref()
+            .is_some_and(|duress| duress.verify(input));
+        self.duress_matched = duress & !genuine;
+        if genuine | duress {
+            AuthOutcome::Accept
+        } else {
+            AuthOutcome::Reject
+        }
+    }
+
+    fn under_duress(&self) -> bool {
+        self.duress_matched
+    }
+
+    fn reset(&mut self) {
+        self.duress_matched = false;
+    }
+}
+
+/// Numeric code checked against its own hash; anything other than digits is
+/// rejected without hashing.
+pub struct PinAuthenticator {
+    verifier: PasswordHash,
+}
+
+impl PinAuthenticator {
+    pub fn new(verifier: PasswordHash) -> Self {
+        Self { verifier }
+    }
+}
+
+impl Authenticator for PinAuthenticator {
+    fn prompt(&self) -> String {
+        "PIN".to_string()
+    }
+
+    fn verify(&mut self, input: &str) -> AuthOutcome {
+        if input.is_empty() || !input.chars().all(|c| c.is_ascii_digit()) {
+            return AuthOutcome::Reject;
+        }
+        if self.verifier.verify(input) {
+            AuthOutcome::Accept
+        } else {
+            AuthOutcome::Reject
+        }
+    }
+}
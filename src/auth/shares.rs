@@ -0,0 +1,89 @@
+use rand::{RngCore, rngs::OsRng};
+use zeroize::Zeroizing;
+
+use super::{AuthOutcome, Authenticator, AuthenticatorConfig};
+use crate::{
+    password::PasswordHash,
+    secret::SecretString,
+    settings::Settings,
+    shamir::{Share, combine_shares, split_secret},
+};
+
+const SHARED_SECRET_LEN: usize = 16;
+
+/// Collects Shamir shares one per submission until `threshold` distinct ones
+/// are in, then checks the rebuilt secret against its hash. A malformed or
+/// repeated share, or a wrong result, starts the collection over.
+pub struct ShareAuthenticator {
+    threshold: u8,
+    verifier: PasswordHash,
+    collected: Vec<Share>,
+}
+
+impl ShareAuthenticator {
+    pub fn new(threshold: u8, verifier: PasswordHash) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            verifier,
+            collected: Vec::with_capacity(threshold as usize),
+        }
+    }
+}
+
+impl Authenticator for ShareAuthenticator {
+    fn prompt(&self) -> String {
+        match self.collected.len() {
+            0 => format!("Share 1 of {}", self.threshold),
+            accepted => format!(
+                "Share {accepted} of {} accepted, next share",
+                self.threshold
+            ),
+        }
+    }
+
+    fn verify(&mut self, input: &str) -> AuthOutcome {
+        let Some(share) = Share::parse(input).filter(|share| {
+            self.collected
+                .iter()
+                .all(|other| other.index != share.index)
+        }) else {
+            self.reset();
+            return AuthOutcome::Reject;
+        };
+        self.collected.push(share);
+        if self.collected.len() < self.threshold as usize {
+            return AuthOutcome::NeedMoreInput;
+        }
+        let secret = combine_shares(&self.collected);
+        self.reset();
+        match secret.map(|secret| Zeroizing::new(hex::encode(&*secret))) {
+            Some(encoded) if self.verifier.verify(&encoded) => AuthOutcome::Accept,
+            _ => AuthOutcome::Reject,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.collected.clear();
+    }
+}
+
+/// Adds (or replaces) the share factor after the existing ones and returns
+/// the `count` shares to hand out. Returns `None` unless
+/// `1 <= threshold <= count`.
+pub fn enroll_shares(
+    settings: &mut Settings,
+    threshold: u8,
+    count: u8,
+) -> Option<Vec<SecretString>> {
+    let mut secret = Zeroizing::new([0u8; SHARED_SECRET_LEN]);
+    OsRng.fill_bytes(&mut *secret);
+    let shares = split_secret(&*secret, threshold, count)?;
+    settings
+        .authenticators
+        .retain(|config| !matches!(config, AuthenticatorConfig::Shares { .. }));
+    settings.authenticators.push(AuthenticatorConfig::Shares {
+        threshold,
+        hash: PasswordHash::new(&Zeroizing::new(hex::encode(*secret))),
+    });
+    Some(shares.iter().map(Share::encode).collect())
+}
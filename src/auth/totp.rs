@@ -0,0 +1,227 @@
+use hmac::{Hmac, Mac};
+use rand::{RngCore, rngs::OsRng};
+use sha1::Sha1;
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{AuthOutcome, Authenticator, AuthenticatorConfig};
+use zeroize::Zeroizing;
+
+use crate::{password::constant_time_eq, secret::SecretString, settings::Settings};
+
+pub const TOTP_ISSUER: &str = "DwmLock";
+pub const DEFAULT_TOTP_DIGITS: u32 = 6;
+pub const DEFAULT_TOTP_PERIOD_SECS: u64 = 30;
+pub const DEFAULT_TOTP_SKEW_STEPS: u64 = 1;
+/// Larger skews are cut to this, so a typo in the settings file cannot make
+/// every submit compute millions of codes.
+pub const MAX_TOTP_SKEW_STEPS: u64 = 10;
+const SECRET_LEN: usize = 20;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// RFC 6238 verifier. `skew_steps` accepts codes from that many periods before
+/// or after the current one; a code is never accepted twice, and neither is an
+/// older code once a newer one was used. The last used step is saved through
+/// `record_use` so this also holds across lock screens.
+pub struct TotpAuthenticator {
+    secret: Zeroizing<Vec<u8>>,
+    digits: u32,
+    period_secs: u64,
+    skew_steps: u64,
+    last_used_step: Option<u64>,
+}
+
+impl TotpAuthenticator {
+    pub fn new(secret: Vec<u8>, digits: u32, period_secs: u64, skew_steps: u64) -> Self {
+        Self {
+            secret: Zeroizing::new(secret),
+            digits: digits.clamp(6, 8),
+            period_secs: period_secs.max(1),
+            skew_steps: skew_steps.min(MAX_TOTP_SKEW_STEPS),
+            last_used_step: None,
+        }
+    }
+
+    /// Restores the step saved by an earlier lock screen.
+    pub fn with_last_used_step(mut self, last_used_step: Option<u64>) -> Self {
+        self.last_used_step = last_used_step;
+        self
+    }
+
+    pub fn verify_at(&mut self, code: &str, unix_secs: u64) -> AuthOutcome {
+        let code = code.trim();
+        if code.len() != self.digits as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return AuthOutcome::Reject;
+        }
+        let current = unix_secs / self.period_secs;
+        let first = current.saturating_sub(self.skew_steps);
+        let mut matched = None;
+        for step in first..=current.saturating_add(self.skew_steps) {
+            let expected = totp_code(&self.secret, step, self.digits);
+            // Check every step so timing does not reveal which window matched.
+            if constant_time_eq(expected.as_bytes(), code.as_bytes()) && matched.is_none() {
+                matched = Some(step);
+            }
+        }
+        match matched {
+            Some(step) if self.last_used_step.is_none_or(|last| step > last) => {
+                self.last_used_step = Some(step);
+                AuthOutcome::Accept
+            }
+            _ => AuthOutcome::Reject,
+        }
+    }
+}
+
+impl fmt::Debug for TotpAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TotpAuthenticator")
+            .field("secret", &"<redacted>")
+            .field("digits", &self.digits)
+            .field("period_secs", &self.period_secs)
+            .field("skew_steps", &self.skew_steps)
+            .field("last_used_step", &self.last_used_step)
+            .finish()
+    }
+}
+
+impl Authenticator for TotpAuthenticator {
+    fn prompt(&self) -> String {
+        "Authenticator code".to_string()
+    }
+
+    fn verify(&mut self, input: &str) -> AuthOutcome {
+        self.verify_at(input, unix_now())
+    }
+
+    fn record_use(&self, config: &mut AuthenticatorConfig) -> bool {
+        let AuthenticatorConfig::Totp {
+            secret,
+            last_used_step,
+            ..
+        } = config
+        else {
+            return false;
+        };
+        let same_secret = base32_decode(secret.expose())
+            .is_some_and(|bytes| constant_time_eq(&bytes, &self.secret));
+        if !same_secret || self.last_used_step <= *last_used_step {
+            return false;
+        }
+        *last_used_step = self.last_used_step;
+        true
+    }
+}
+
+pub fn totp_code(secret: &[u8], step: u64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("hmac accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    let modulus = 10u64.pow(digits);
+    format!(
+        "{:0width$}",
+        binary as u64 % modulus,
+        width = digits as usize
+    )
+}
+
+pub fn generate_secret() -> SecretString {
+    let mut secret = Zeroizing::new([0u8; SECRET_LEN]);
+    OsRng.fill_bytes(secret.as_mut());
+    SecretString::new(base32_encode(secret.as_ref()))
+}
+
+/// `otpauth://` URI understood by common authenticator apps for enrollment.
+pub fn provisioning_uri(secret: &str, account: &str, digits: u32, period_secs: u64) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period_secs}",
+        issuer = TOTP_ISSUER,
+        account = percent_encode(account),
+    )
+}
+
+/// Adds (or replaces) the TOTP factor after the existing ones and returns the
+/// enrollment URI for the new secret.
+pub fn enroll_totp(settings: &mut Settings, account: &str) -> String {
+    let secret = generate_secret();
+    settings
+        .authenticators
+        .retain(|config| !matches!(config, AuthenticatorConfig::Totp { .. }));
+    let uri = provisioning_uri(
+        secret.expose(),
+        account,
+        DEFAULT_TOTP_DIGITS,
+        DEFAULT_TOTP_PERIOD_SECS,
+    );
+    settings.authenticators.push(AuthenticatorConfig::Totp {
+        secret,
+        digits: DEFAULT_TOTP_DIGITS,
+        period_secs: DEFAULT_TOTP_PERIOD_SECS,
+        skew_steps: DEFAULT_TOTP_SKEW_STEPS,
+        last_used_step: None,
+    });
+    uri
+}
+
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+pub fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for ch in text.chars().filter(|c| !matches!(c, ' ' | '-' | '=')) {
+        let upper = ch.to_ascii_uppercase() as u8;
+        let value = BASE32_ALPHABET.iter().position(|&c| c == upper)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'@') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,117 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Longest delay or lockout a policy can impose. Larger settings are cut down
+/// to this, so a typo cannot overflow the clock or keep the machine locked
+/// for good.
+pub const MAX_BACKOFF_SECS: u64 = 24 * 60 * 60;
+
+/// How long to refuse input after consecutive failed unlock attempts. The
+/// first `free_attempts` failures cost nothing, later ones double the delay
+/// up to `max_delay_secs`, and reaching `lockout_after` failures imposes the
+/// hard `lockout_secs` cooldown for every further failure.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(default)]
+pub struct BackoffPolicy {
+    pub free_attempts: u32,
+    #[schemars(range(max = MAX_BACKOFF_SECS))]
+    pub base_delay_secs: u64,
+    #[schemars(range(max = MAX_BACKOFF_SECS))]
+    pub max_delay_secs: u64,
+    pub lockout_after: u32,
+    #[schemars(range(max = MAX_BACKOFF_SECS))]
+    pub lockout_secs: u64,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            free_attempts: 3,
+            base_delay_secs: 2,
+            max_delay_secs: 60,
+            lockout_after: 10,
+            lockout_secs: 300,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// `self` with every delay limited to `MAX_BACKOFF_SECS`.
+    pub fn clamped(self) -> Self {
+        Self {
+            base_delay_secs: self.base_delay_secs.min(MAX_BACKOFF_SECS),
+            max_delay_secs: self.max_delay_secs.min(MAX_BACKOFF_SECS),
+            lockout_secs: self.lockout_secs.min(MAX_BACKOFF_SECS),
+            ..self
+        }
+    }
+
+    pub fn delay_after(&self, failures: u32) -> Duration {
+        if self.lockout_after > 0 && failures >= self.lockout_after {
+            return Duration::from_secs(self.lockout_secs.min(MAX_BACKOFF_SECS));
+        }
+        if failures <= self.free_attempts {
+            return Duration::ZERO;
+        }
+        let exponent = (failures - self.free_attempts - 1).min(31);
+        let delay = self
+            .base_delay_secs
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_delay_secs)
+            .min(MAX_BACKOFF_SECS);
+        Duration::from_secs(delay)
+    }
+}
+
+/// Failed-attempt bookkeeping. Every method takes `now` so callers decide
+/// which clock drives it.
+#[derive(Debug, Clone)]
+pub struct AttemptTracker {
+    policy: BackoffPolicy,
+    failures: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl AttemptTracker {
+    pub fn new(policy: BackoffPolicy) -> Self {
+        Self {
+            policy: policy.clamped(),
+            failures: 0,
+            blocked_until: None,
+        }
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        self.failures = self.failures.saturating_add(1);
+        let delay = self.policy.delay_after(self.failures);
+        if delay.is_zero() {
+            self.blocked_until = None;
+            return;
+        }
+        // Runs inside the window procedure, where a panic would end the lock.
+        self.blocked_until = now
+            .checked_add(delay)
+            .or_else(|| now.checked_add(Duration::from_secs(MAX_BACKOFF_SECS)))
+            .or(self.blocked_until);
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.blocked_until = None;
+    }
+
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.blocked_until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|left| !left.is_zero())
+    }
+
+    pub fn is_blocked(&self, now: Instant) -> bool {
+        self.remaining(now).is_some()
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
@@ -0,0 +1,402 @@
+//! Portable settings bundles for rolling one configuration out to many
+//! machines. A bundle carries the user's settings without secrets or state
+//! that only makes sense on the machine it came from; importing one replaces
+//! the settings it names and keeps everything else, including the local
+//! password and unlock factors. Bundles carry settings only; the lock screen
+//! has no themes or image assets that would need to travel with them.
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{fmt, fs, io, path::Path};
+
+use crate::{
+    layers::{SECRET_KEYS, get_setting},
+    migrations::{MigrationError, migrate_settings_value},
+    schema::settings_schema,
+    settings::{Settings, changed_fields, write_atomically},
+};
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Settings that describe this machine rather than the configuration, so
+/// they are left out of bundles just like `SECRET_KEYS`.
+pub const MACHINE_KEYS: &[&str] = &[
+    "guest_session",
+    "keyfile",
+    "password_changed_at",
+    "used_token_nonces",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SettingsBundle {
+    pub bundle_version: u32,
+    pub exported_at: String,
+    /// A partial settings file, including its `schema_version`.
+    pub settings: Value,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Read(io::Error),
+    Write(io::Error),
+    Syntax(serde_json::Error),
+    UnsupportedVersion {
+        found: u32,
+        supported: u32,
+    },
+    Schema(MigrationError),
+    /// The bundle parsed but does not fit the settings schema.
+    Invalid(String),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Read(err) => write!(f, "the bundle cannot be read: {err}"),
+            BundleError::Write(err) => write!(f, "the bundle cannot be written: {err}"),
+            BundleError::Syntax(err) => write!(f, "the bundle is not valid JSON: {err}"),
+            BundleError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "the bundle uses format version {found}, but this build only understands up to {supported}"
+            ),
+            BundleError::Schema(err) => write!(f, "the bundle's settings: {err}"),
+            BundleError::Invalid(message) => write!(f, "the bundle is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+/// One setting an import would change. Values are shown as `get_setting`
+/// shows them; `None` means the setting is absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub key: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// The result of checking a bundle against the local settings: what would
+/// be saved, and what that changes.
+#[derive(Debug, Clone)]
+pub struct ImportPlan {
+    pub settings: Settings,
+    pub changes: Vec<SettingChange>,
+}
+
+impl ImportPlan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// `user` as a bundle. Pass the user's own file rather than layered
+/// settings, so overrides from the policy, environment or command line stay
+/// where they came from.
+pub fn export_bundle(user: &Settings) -> SettingsBundle {
+    let mut settings = serde_json::to_value(user).unwrap_or_else(|_| Value::Object(Map::new()));
+    if let Value::Object(object) = &mut settings {
+        for key in MACHINE_KEYS {
+            object.remove(*key);
+        }
+    }
+    strip_secrets(&mut settings);
+    SettingsBundle {
+        bundle_version: BUNDLE_VERSION,
+        exported_at: Local::now().to_rfc3339(),
+        settings,
+    }
+}
+
+pub fn write_bundle(path: &Path, bundle: &SettingsBundle) -> Result<(), BundleError> {
+    let mut text = serde_json::to_string_pretty(bundle).map_err(BundleError::Syntax)?;
+    text.push('\n');
+    write_atomically(path, text.as_bytes()).map_err(BundleError::Write)
+}
+
+pub fn read_bundle(path: &Path) -> Result<SettingsBundle, BundleError> {
+    parse_bundle(&fs::read(path).map_err(BundleError::Read)?)
+}
+
+pub fn parse_bundle(bytes: &[u8]) -> Result<SettingsBundle, BundleError> {
+    let bundle: SettingsBundle = serde_json::from_slice(bytes).map_err(BundleError::Syntax)?;
+    if bundle.bundle_version > BUNDLE_VERSION {
+        return Err(BundleError::UnsupportedVersion {
+            found: bundle.bundle_version,
+            supported: BUNDLE_VERSION,
+        });
+    }
+    Ok(bundle)
+}
+
+/// Checks `bundle` against the settings schema and works out the settings
+/// it would produce from `local`. Nothing is written, so the caller can show
+/// the changes first and save `ImportPlan::settings` in one step.
+pub fn plan_import(local: &Settings, bundle: &SettingsBundle) -> Result<ImportPlan, BundleError> {
+    let mut incoming = bundle.settings.clone();
+    migrate_settings_value(&mut incoming).map_err(BundleError::Schema)?;
+    let Value::Object(incoming) = incoming else {
+        return Err(BundleError::Schema(MigrationError::NotAnObject));
+    };
+    check_against_schema(&incoming)?;
+
+    let local_value =
+        serde_json::to_value(local).map_err(|err| BundleError::Invalid(err.to_string()))?;
+    let mut merged = local_value.clone();
+    if let Value::Object(object) = &mut merged {
+        object.extend(incoming);
+    }
+    keep_local_secrets(&mut merged, &local_value);
+    let mut settings: Settings = serde_json::from_value(merged)
+        .map_err(|err| BundleError::Invalid(format!("the settings do not load: {err}")))?;
+    settings.layers = local.layers.clone();
+
+    let changes = changed_fields(local, &settings)
+        .into_iter()
+        .map(|key| SettingChange {
+            before: get_setting(local, &key),
+            after: get_setting(&settings, &key),
+            key,
+        })
+        .collect();
+    Ok(ImportPlan { settings, changes })
+}
+
+/// Secrets and machine state are refused up front; everything else is
+/// checked against the published schema at every depth, so a typo inside a
+/// profile is caught just like one at the top level.
+fn check_against_schema(incoming: &Map<String, Value>) -> Result<(), BundleError> {
+    let schema = settings_schema();
+    for (key, value) in incoming {
+        let Some(property) = schema["properties"].get(key) else {
+            return Err(BundleError::Invalid(format!("{key:?} is not a setting")));
+        };
+        // `writeOnly` marks the legacy plaintext `password`.
+        if MACHINE_KEYS.contains(&key.as_str())
+            || property["writeOnly"] == true
+            || SECRET_KEYS.contains(&key.as_str())
+            || contains_secret(value)
+        {
+            return Err(BundleError::Invalid(format!(
+                "{key:?} holds secrets or machine-specific state and cannot be imported"
+            )));
+        }
+        check_value(value, property, &schema, key).map_err(BundleError::Invalid)?;
+    }
+    Ok(())
+}
+
+/// The subset of JSON Schema that schemars emits for the settings types:
+/// `$ref`, `anyOf`, `oneOf`, `const`, `type`, numeric bounds, `properties`,
+/// `required`, `additionalProperties` and `items`. `path` names the value in
+/// messages, with dots for nested keys.
+fn check_value(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .ok_or_else(|| format!("{path} refers to the unknown schema {reference}"))?;
+        check_value(value, target, root, path)?;
+    }
+    if let Some(branches) = schema["anyOf"].as_array() {
+        check_alternatives(value, branches, root, path)?;
+    }
+    if let Some(branches) = schema["oneOf"].as_array() {
+        check_alternatives(value, branches, root, path)?;
+    }
+    if let Some(expected) = schema.get("const")
+        && value != expected
+    {
+        return Err(format!("{path} must be {expected}"));
+    }
+    if let Some(types) = schema.get("type")
+        && !type_matches(value, types)
+    {
+        return Err(format!("{path} must be of type {types}"));
+    }
+    if let Some(number) = value.as_f64() {
+        let below = schema["minimum"].as_f64().is_some_and(|min| number < min);
+        let above = schema["maximum"].as_f64().is_some_and(|max| number > max);
+        if below || above {
+            return Err(match (&schema["minimum"], &schema["maximum"]) {
+                (Value::Null, max) => format!("{path} must be at most {max}"),
+                (min, Value::Null) => format!("{path} must be at least {min}"),
+                (min, max) => format!("{path} must be between {min} and {max}"),
+            });
+        }
+    }
+    match value {
+        Value::Object(object) => check_object(object, schema, root, path),
+        Value::Array(items) => match schema.get("items") {
+            Some(item_schema) => items.iter().enumerate().try_for_each(|(index, item)| {
+                check_value(item, item_schema, root, &format!("{path}[{index}]"))
+            }),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn check_object(
+    object: &Map<String, Value>,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(required) = schema["required"].as_array() {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(format!("{path}.{key} is missing"));
+            }
+        }
+    }
+    let properties = schema["properties"].as_object();
+    let additional = schema.get("additionalProperties");
+    if properties.is_none() && additional.is_none() {
+        return Ok(());
+    }
+    for (key, value) in object {
+        let nested = format!("{path}.{key}");
+        match (
+            properties.and_then(|properties| properties.get(key)),
+            additional,
+        ) {
+            (Some(property), _) => check_value(value, property, root, &nested)?,
+            (None, Some(Value::Bool(true))) => {}
+            (None, Some(additional)) if additional.is_object() => {
+                check_value(value, additional, root, &nested)?
+            }
+            _ => return Err(format!("{nested:?} is not a setting")),
+        }
+    }
+    Ok(())
+}
+
+/// Passes when any branch accepts `value`. Branches that all pin the same
+/// property to a `const`, like the `kind` of a tagged enum, are told apart
+/// by that property so the error comes from the branch that was meant.
+fn check_alternatives(
+    value: &Value,
+    branches: &[Value],
+    root: &Value,
+    path: &str,
+) -> Result<(), String> {
+    let resolved: Vec<&Value> = branches
+        .iter()
+        .map(|branch| {
+            branch["$ref"]
+                .as_str()
+                .and_then(|reference| reference.strip_prefix('#'))
+                .and_then(|pointer| root.pointer(pointer))
+                .unwrap_or(branch)
+        })
+        .collect();
+    let mut first_error = None;
+    for branch in &resolved {
+        match check_value(value, branch, root, path) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if resolved.iter().all(|branch| branch.get("const").is_some()) {
+        let allowed: Vec<String> = resolved
+            .iter()
+            .map(|branch| branch["const"].to_string())
+            .collect();
+        return Err(format!("{path} must be one of {}", allowed.join(", ")));
+    }
+    if let Some(tag) = shared_tag(&resolved) {
+        let tag_schema = |branch: &Value| branch["properties"][tag]["const"].clone();
+        let matching = resolved
+            .iter()
+            .find(|branch| value.get(tag) == Some(&tag_schema(branch)));
+        return match matching {
+            Some(branch) => check_value(value, branch, root, path),
+            None => {
+                let allowed: Vec<String> = resolved
+                    .iter()
+                    .map(|branch| tag_schema(branch).to_string())
+                    .collect();
+                Err(format!(
+                    "{path}.{tag} must be one of {}",
+                    allowed.join(", ")
+                ))
+            }
+        };
+    }
+    Err(first_error.unwrap_or_else(|| format!("{path} does not match the schema")))
+}
+
+/// A property every branch pins to a `const`, such as `kind`.
+fn shared_tag<'a>(branches: &[&'a Value]) -> Option<&'a str> {
+    let first = branches.first()?["properties"].as_object()?;
+    first
+        .iter()
+        .find(|(key, property)| {
+            property.get("const").is_some()
+                && branches
+                    .iter()
+                    .all(|branch| branch["properties"][key.as_str()].get("const").is_some())
+        })
+        .map(|(key, _)| key.as_str())
+}
+
+fn type_matches(value: &Value, types: &Value) -> bool {
+    let matches = |name: &str| match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    };
+    match types {
+        Value::String(name) => matches(name),
+        Value::Array(names) => names.iter().filter_map(Value::as_str).any(matches),
+        _ => true,
+    }
+}
+
+/// Removes every secret field inside `value`, wherever it appears.
+fn strip_secrets(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|key, _| !SECRET_KEYS.contains(&key.as_str()));
+            object.values_mut().for_each(strip_secrets);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_secrets),
+        _ => {}
+    }
+}
+
+fn contains_secret(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => object
+            .iter()
+            .any(|(key, inner)| SECRET_KEYS.contains(&key.as_str()) || contains_secret(inner)),
+        Value::Array(items) => items.iter().any(contains_secret),
+        _ => false,
+    }
+}
+
+/// Copies secret fields from `local` into objects of `merged` that lost
+/// them, such as the authenticators of a profile the bundle redefines.
+fn keep_local_secrets(merged: &mut Value, local: &Value) {
+    let (Value::Object(merged), Value::Object(local)) = (merged, local) else {
+        return;
+    };
+    for (key, local_value) in local {
+        if SECRET_KEYS.contains(&key.as_str()) {
+            if !merged.contains_key(key) {
+                merged.insert(key.clone(), local_value.clone());
+            }
+        } else if let Some(merged_value) = merged.get_mut(key) {
+            keep_local_secrets(merged_value, local_value);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use hmac::{Hmac, Mac};
+use rand::{Rng, rngs::OsRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+
+use crate::{
+    auth::totp::{base32_decode, generate_secret},
+    password::constant_time_eq,
+    secret::SecretString,
+};
+
+pub const DEFAULT_CHALLENGE_AFTER_FAILURES: u32 = 5;
+pub const DEFAULT_CHALLENGE_EXPIRY_SECS: u64 = 600;
+/// Keeps the HMAC key specific to this use even if the secret is shared.
+const RESPONSE_CONTEXT: &[u8] = b"dwmlock-challenge-v1\n";
+const CHALLENGE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CHALLENGE_LEN: usize = 8;
+const RESPONSE_DIGITS: u32 = 8;
+
+/// Shared secret (base32) known to this machine and the helpdesk. After
+/// `after_failures` failed attempts the warning screen shows a challenge that
+/// stays answerable for `expiry_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ChallengeConfig {
+    pub secret: SecretString,
+    #[serde(default = "default_after_failures")]
+    pub after_failures: u32,
+    #[serde(default = "default_expiry_secs")]
+    pub expiry_secs: u64,
+}
+
+impl ChallengeConfig {
+    pub fn generate() -> Self {
+        Self {
+            secret: generate_secret(),
+            after_failures: DEFAULT_CHALLENGE_AFTER_FAILURES,
+            expiry_secs: DEFAULT_CHALLENGE_EXPIRY_SECS,
+        }
+    }
+
+    pub fn expiry(&self) -> Duration {
+        Duration::from_secs(self.expiry_secs)
+    }
+
+    /// The code helpdesk reads back for `challenge`, or `None` when the
+    /// secret does not decode.
+    pub fn response(&self, challenge: &str) -> Option<String> {
+        let key = base32_decode(self.secret.expose()).filter(|key| !key.is_empty())?;
+        Some(challenge_response(&key, challenge))
+    }
+}
+
+/// A challenge on screen, issued at `issued`. The code is random and
+/// single-purpose, so it is not treated as a secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub code: String,
+    pub issued: Instant,
+}
+
+impl Challenge {
+    pub fn new(now: Instant) -> Self {
+        let mut code = String::with_capacity(CHALLENGE_LEN + 1);
+        for position in 0..CHALLENGE_LEN {
+            if position == CHALLENGE_LEN / 2 {
+                code.push('-');
+            }
+            let index = OsRng.gen_range(0..CHALLENGE_ALPHABET.len());
+            code.push(CHALLENGE_ALPHABET[index] as char);
+        }
+        Self { code, issued: now }
+    }
+
+    pub fn remaining(&self, expiry: Duration, now: Instant) -> Option<Duration> {
+        expiry
+            .checked_sub(now.saturating_duration_since(self.issued))
+            .filter(|left| !left.is_zero())
+    }
+
+    pub fn is_expired(&self, expiry: Duration, now: Instant) -> bool {
+        self.remaining(expiry, now).is_none()
+    }
+}
+
+/// Whether a new challenge should go on screen: enough attempts have failed
+/// and there is no challenge yet, or the current one has expired.
+pub fn challenge_due(
+    config: &ChallengeConfig,
+    failures: u32,
+    current: Option<&Challenge>,
+    now: Instant,
+) -> bool {
+    failures >= config.after_failures
+        && current.is_none_or(|challenge| challenge.is_expired(config.expiry(), now))
+}
+
+/// HMAC-SHA256 over the canonical challenge, cut down to eight digits shown
+/// as `1234-5678` so it is easy to read out over the phone.
+pub fn challenge_response(key: &[u8], challenge: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(RESPONSE_CONTEXT);
+    mac.update(canonicalize(challenge).as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let value = u32::from_be_bytes([digest[0] & 0x7f, digest[1], digest[2], digest[3]]);
+    let code = value % 10u32.pow(RESPONSE_DIGITS);
+    let half = 10u32.pow(RESPONSE_DIGITS / 2);
+    format!("{:04}-{:04}", code / half, code % half)
+}
+
+/// Compares a typed response with the expected one in constant time,
+/// ignoring dashes and spaces.
+pub fn verify_response(config: &ChallengeConfig, challenge: &Challenge, input: &str) -> bool {
+    let Some(expected) = config.response(&challenge.code) else {
+        return false;
+    };
+    constant_time_eq(
+        canonicalize(&expected).as_bytes(),
+        canonicalize(input).as_bytes(),
+    )
+}
+
+fn canonicalize(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn default_after_failures() -> u32 {
+    DEFAULT_CHALLENGE_AFTER_FAILURES
+}
+
+fn default_expiry_secs() -> u64 {
+    DEFAULT_CHALLENGE_EXPIRY_SECS
+}
@@ -0,0 +1,449 @@
+//! Command-line parsing. Kept free of Win32 so it builds and is tested on any
+//! platform; `main.rs` only dispatches on the parsed `Command`.
+
+use serde_json::Value;
+use std::fmt;
+
+use crate::{layers::parse_override_value, secret::SecretString};
+
+pub const EXIT_SUCCESS: i32 = 0;
+/// The command ran but failed, e.g. the settings file is invalid.
+pub const EXIT_FAILURE: i32 = 1;
+/// The command line itself was wrong.
+pub const EXIT_USAGE: i32 = 2;
+/// The user declined the lock confirmation.
+pub const EXIT_CANCELLED: i32 = 3;
+
+/// The longest a guest password from `--add-guest-password` may stay valid.
+pub const MAX_GUEST_HOURS: f64 = 24.0 * 366.0;
+
+/// The longest an unlock token from `token sign` may stay valid.
+pub const MAX_TOKEN_HOURS: u64 = 24 * 366;
+
+pub const PROFILE_FLAG: &str = "--profile";
+pub const SET_FLAG: &str = "--set";
+
+pub const MAIN_HELP: &str = "\
+Usage: dwmlock [--profile <name>] [--set <key>=<value>]... [command]
+
+Commands:
+  lock              Lock the screen (the default)
+  settings          Show or change settings (get, set, list, reset)
+  config            Check the settings file or show where it lives
+  hash-password     Print the stored form of a password
+  schema            Print the JSON Schema of the settings file
+  token             Create keys and signed unlock tokens
+  respond           Answer a helpdesk challenge
+  version           Print the version
+  help [command]    Show help for a command
+
+Options:
+  --profile <name>      Apply a named profile from the settings file
+  --set <key>=<value>   Override a setting for this run only
+
+Exit status: 0 success, 1 failure, 2 usage error, 3 lock declined.";
+
+pub const LOCK_HELP: &str = "\
+Usage: dwmlock lock [--yes] [--settings]
+
+  --yes, -y     Lock without asking for confirmation
+  --settings    Open the settings dialog before locking
+
+Setup options, run instead of locking and given on their own:
+  --enroll-totp
+  --generate-recovery-codes
+  --enable-challenge
+  --generate-shares <threshold> <count>
+  --set-keyfile <keyfile> [path or pattern]...
+  --add-guest-password <label> <hours> [uses]";
+
+pub const SETTINGS_HELP: &str = "\
+Usage: dwmlock settings <list | get <key> | set <key> <value> | reset [key]>
+       dwmlock settings <export <file> | import <file> [--dry-run]>
+
+  list              Every setting with its value and where it came from
+  get <key>         One value; nested keys use dots (password_policy.min_length)
+  set <key> <value> Store a value in the user's settings file; values are JSON
+                    when they parse as JSON and plain text otherwise
+  reset [key]       Restore one key, or everything but the password, to the
+                    built-in defaults
+  export <file>     Write the settings to a bundle for other machines
+  import <file>     Apply a bundle, listing every change it makes; with
+                    --dry-run, only list them
+
+Secrets and password hashes are shown as hidden, and bundles leave them out
+along with other machine-specific state. Keys set by the machine policy
+cannot be changed.";
+
+pub const CONFIG_HELP: &str = "\
+Usage: dwmlock config <check | path>
+
+  check   Validate the settings file without changing it (exit status 1 if invalid)
+  path    Print where the settings file and the machine policy live";
+
+pub const HASH_PASSWORD_HELP: &str = "\
+Usage: dwmlock hash-password [password]
+
+Prints the password hash as JSON, ready for `password_hash` in a settings or
+policy file. Reads the password from standard input when it is not given.";
+
+pub const SCHEMA_HELP: &str = "\
+Usage: dwmlock schema
+
+Prints the JSON Schema of dwmlock_settings.json, for editors and for
+checking generated settings files before they are deployed.";
+
+pub const TOKEN_HELP: &str = "\
+Usage: dwmlock token <keygen | sign <private key file> [machine] [hours]>
+
+  keygen    Print a new private and public key (hex)
+  sign      Print an unlock token for `machine` (default: this one) that is
+            valid for `hours` (default: 1, at most 8784)";
+
+pub const RESPOND_HELP: &str = "\
+Usage: dwmlock respond <challenge>
+
+Prints the helpdesk response to a challenge shown on the lock screen, using
+the shared secret in this machine's settings file.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Lock {
+        yes: bool,
+        open_settings: bool,
+    },
+    SettingsList,
+    SettingsGet {
+        key: String,
+    },
+    SettingsSet {
+        key: String,
+        value: Value,
+    },
+    SettingsReset {
+        key: Option<String>,
+    },
+    SettingsExport {
+        file: String,
+    },
+    SettingsImport {
+        file: String,
+        dry_run: bool,
+    },
+    ConfigCheck,
+    ConfigPath,
+    HashPassword {
+        password: Option<SecretString>,
+    },
+    Schema,
+    Version,
+    Help(&'static str),
+    TokenKeygen,
+    TokenSign {
+        key_file: String,
+        machine: Option<String>,
+        hours: u64,
+    },
+    Respond {
+        challenge: String,
+    },
+    EnrollTotp,
+    GenerateRecoveryCodes,
+    EnableChallenge,
+    GenerateShares {
+        threshold: u8,
+        count: u8,
+    },
+    SetKeyfile {
+        keyfile: String,
+        patterns: Vec<String>,
+    },
+    AddGuestPassword {
+        label: String,
+        hours: f64,
+        uses: Option<u32>,
+    },
+}
+
+/// Options that apply to every command because they shape the settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlobalOptions {
+    pub profile: Option<String>,
+    pub overrides: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub command: Command,
+    pub global: GlobalOptions,
+}
+
+/// What was wrong with the command line, plus the help text to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError {
+    pub message: String,
+    pub help: &'static str,
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.message, self.help)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+fn usage(message: impl Into<String>, help: &'static str) -> UsageError {
+    UsageError {
+        message: message.into(),
+        help,
+    }
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: &[String]) -> Result<Invocation, UsageError> {
+    let (global, rest) = split_global_options(args)?;
+    let command = parse_command(&rest)?;
+    Ok(Invocation { command, global })
+}
+
+/// Pulls `--profile` and `--set` out of `args`, wherever they appear.
+fn split_global_options(args: &[String]) -> Result<(GlobalOptions, Vec<&str>), UsageError> {
+    let mut global = GlobalOptions::default();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            PROFILE_FLAG => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| usage(format!("{PROFILE_FLAG} expects a name"), MAIN_HELP))?;
+                global.profile = Some(name.clone());
+            }
+            SET_FLAG => {
+                let assignment = args.next().and_then(|pair| pair.split_once('='));
+                let Some((key, raw)) = assignment.filter(|(key, _)| !key.trim().is_empty()) else {
+                    return Err(usage(format!("{SET_FLAG} expects key=value"), MAIN_HELP));
+                };
+                global
+                    .overrides
+                    .push((key.trim().to_string(), parse_override_value(raw)));
+            }
+            other => rest.push(other),
+        }
+    }
+    Ok((global, rest))
+}
+
+fn parse_command(args: &[&str]) -> Result<Command, UsageError> {
+    let Some((&first, rest)) = args.split_first() else {
+        return Ok(Command::Lock {
+            yes: false,
+            open_settings: false,
+        });
+    };
+    // Only right after a command word; later positions hold values, such as
+    // `settings set lock_message -h`. `parse_lock` handles its own flags.
+    if !first.starts_with('-') && rest.first().is_some_and(|arg| is_help_flag(arg)) {
+        return Ok(Command::Help(help_for(first).unwrap_or(MAIN_HELP)));
+    }
+    match first {
+        "lock" => parse_lock(rest),
+        "settings" => parse_settings_command(rest),
+        "config" => match rest {
+            ["check"] => Ok(Command::ConfigCheck),
+            ["path"] => Ok(Command::ConfigPath),
+            _ => Err(usage("config expects check or path", CONFIG_HELP)),
+        },
+        "hash-password" => match rest {
+            [] => Ok(Command::HashPassword { password: None }),
+            [password] => Ok(Command::HashPassword {
+                password: Some(SecretString::from(*password)),
+            }),
+            _ => Err(usage(
+                "hash-password takes at most one password",
+                HASH_PASSWORD_HELP,
+            )),
+        },
+        "schema" => match rest {
+            [] => Ok(Command::Schema),
+            _ => Err(usage("schema takes no arguments", SCHEMA_HELP)),
+        },
+        "version" | "--version" | "-V" => Ok(Command::Version),
+        "help" | "--help" | "-h" => match rest {
+            [] => Ok(Command::Help(MAIN_HELP)),
+            [topic] => help_for(topic)
+                .map(Command::Help)
+                .ok_or_else(|| usage(format!("no help for {topic:?}"), MAIN_HELP)),
+            _ => Err(usage("help takes at most one command", MAIN_HELP)),
+        },
+        "token" => parse_token(rest),
+        "respond" => match rest {
+            [challenge] => Ok(Command::Respond {
+                challenge: challenge.to_string(),
+            }),
+            _ => Err(usage("respond expects a challenge", RESPOND_HELP)),
+        },
+        "--check-config" if rest.is_empty() => Ok(Command::ConfigCheck),
+        flag if flag.starts_with('-') => parse_lock(args),
+        other => Err(usage(format!("unknown command {other:?}"), MAIN_HELP)),
+    }
+}
+
+fn is_help_flag(arg: &str) -> bool {
+    matches!(arg, "--help" | "-h")
+}
+
+fn help_for(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "lock" => LOCK_HELP,
+        "settings" => SETTINGS_HELP,
+        "config" => CONFIG_HELP,
+        "hash-password" => HASH_PASSWORD_HELP,
+        "schema" => SCHEMA_HELP,
+        "token" => TOKEN_HELP,
+        "respond" => RESPOND_HELP,
+        "version" | "help" => MAIN_HELP,
+        _ => return None,
+    })
+}
+
+/// Flags for `lock`. The setup options predate subcommands and are still
+/// accepted on their own, as in `dwmlock --enroll-totp`; anything beside a
+/// setup option and its own arguments is refused rather than ignored.
+fn parse_lock(args: &[&str]) -> Result<Command, UsageError> {
+    let mut flags = Vec::new();
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        let setup = match arg {
+            "--yes" | "-y" | "--settings" | "--open-settings" | "--settings-on-startup" => {
+                flags.push(arg);
+                continue;
+            }
+            "--help" | "-h" => return Ok(Command::Help(LOCK_HELP)),
+            "--enroll-totp" => Command::EnrollTotp,
+            "--generate-recovery-codes" => Command::GenerateRecoveryCodes,
+            "--enable-challenge" => Command::EnableChallenge,
+            "--generate-shares" => {
+                let mut number = || args.next().and_then(|arg| arg.parse::<u8>().ok());
+                match (number(), number()) {
+                    (Some(threshold), Some(count)) => Command::GenerateShares { threshold, count },
+                    _ => {
+                        return Err(usage(
+                            "--generate-shares expects <threshold> <count>",
+                            LOCK_HELP,
+                        ));
+                    }
+                }
+            }
+            "--set-keyfile" => {
+                let Some(keyfile) = args.next() else {
+                    return Err(usage("--set-keyfile expects a keyfile", LOCK_HELP));
+                };
+                Command::SetKeyfile {
+                    keyfile: keyfile.to_string(),
+                    patterns: args.by_ref().map(str::to_string).collect(),
+                }
+            }
+            "--add-guest-password" => {
+                let rest: Vec<&str> = args.by_ref().collect();
+                parse_guest_args(&rest).ok_or_else(|| {
+                    usage(
+                        "--add-guest-password expects <label> <hours> [uses], with at most a year of hours",
+                        LOCK_HELP,
+                    )
+                })?
+            }
+            other => return Err(usage(format!("unknown option {other:?}"), LOCK_HELP)),
+        };
+        if let Some(other) = flags.first().copied().or_else(|| args.next()) {
+            return Err(usage(
+                format!("{arg} cannot be combined with {other:?}"),
+                LOCK_HELP,
+            ));
+        }
+        return Ok(setup);
+    }
+    Ok(Command::Lock {
+        yes: flags.iter().any(|flag| matches!(*flag, "--yes" | "-y")),
+        open_settings: flags.iter().any(|flag| !matches!(*flag, "--yes" | "-y")),
+    })
+}
+
+fn parse_guest_args(args: &[&str]) -> Option<Command> {
+    let (label, rest) = args.split_first()?;
+    let hours: f64 = rest
+        .first()?
+        .parse()
+        .ok()
+        .filter(|hours: &f64| *hours > 0.0 && *hours <= MAX_GUEST_HOURS)?;
+    let uses = match rest.get(1..)? {
+        [] => None,
+        [uses] => Some(uses.parse().ok()?),
+        _ => return None,
+    };
+    Some(Command::AddGuestPassword {
+        label: label.to_string(),
+        hours,
+        uses,
+    })
+}
+
+fn parse_settings_command(args: &[&str]) -> Result<Command, UsageError> {
+    match args {
+        ["list"] => Ok(Command::SettingsList),
+        ["get", key] => Ok(Command::SettingsGet {
+            key: key.to_string(),
+        }),
+        ["set", key, value] => Ok(Command::SettingsSet {
+            key: key.to_string(),
+            value: parse_override_value(value),
+        }),
+        ["reset"] => Ok(Command::SettingsReset { key: None }),
+        ["reset", key] => Ok(Command::SettingsReset {
+            key: Some(key.to_string()),
+        }),
+        ["export", file] => Ok(Command::SettingsExport {
+            file: file.to_string(),
+        }),
+        ["import", file] | ["import", file, "--dry-run"] | ["import", "--dry-run", file]
+            if *file != "--dry-run" =>
+        {
+            Ok(Command::SettingsImport {
+                file: file.to_string(),
+                dry_run: args.contains(&"--dry-run"),
+            })
+        }
+        _ => Err(usage(
+            "settings expects list, get, set, reset, export or import",
+            SETTINGS_HELP,
+        )),
+    }
+}
+
+fn parse_token(args: &[&str]) -> Result<Command, UsageError> {
+    match args {
+        ["keygen"] => Ok(Command::TokenKeygen),
+        ["sign", key_file, rest @ ..] if rest.len() <= 2 => {
+            let hours = match rest.get(1) {
+                Some(hours) => hours
+                    .parse()
+                    .ok()
+                    .filter(|hours| *hours <= MAX_TOKEN_HOURS)
+                    .ok_or_else(|| {
+                        usage(
+                            format!("hours must be a whole number up to {MAX_TOKEN_HOURS}"),
+                            TOKEN_HELP,
+                        )
+                    })?,
+                None => 1,
+            };
+            Ok(Command::TokenSign {
+                key_file: key_file.to_string(),
+                machine: rest.first().map(|machine| machine.to_string()),
+                hours,
+            })
+        }
+        _ => Err(usage("token expects keygen or sign", TOKEN_HELP)),
+    }
+}
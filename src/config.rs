@@ -1,16 +1,34 @@
 use std::time::Duration;
-use windows::core::{PCWSTR, w};
+#[cfg(windows)]
+use windows::{
+    Win32::UI::WindowsAndMessaging::WM_APP,
+    core::{PCWSTR, w},
+};
 
 pub const DEFAULT_PASSWORD: &str = "media";
 pub const DEFAULT_BLUR_RADIUS: usize = 12;
+pub const MIN_BLUR_RADIUS: usize = 1;
+pub const MAX_BLUR_RADIUS: usize = 64;
 pub const WARNING_DURATION: Duration = Duration::from_secs(5);
 pub const TIMER_ID: usize = 1;
 pub const TIMER_INTERVAL_MS: u32 = 1000;
+/// Posted by the keyfile probe thread. It carries nothing: any process can
+/// post it, so the handler checks the keyfile again itself.
+#[cfg(windows)]
+pub const WM_KEYFILE_FOUND: u32 = WM_APP + 1;
 pub const WARNING_MESSAGE: &str = "Fuck off BITCH";
+#[cfg(windows)]
 pub const CLASS_NAME: PCWSTR = w!("DwmLockMainWindow");
+#[cfg(windows)]
 pub const APPROVAL_PROMPT: PCWSTR = w!(
     "Lock screen now?\nThis will blur the display and capture input until you type the password."
 );
+#[cfg(windows)]
 pub const APPROVAL_CAPTION: PCWSTR = w!("DwmLock consent");
 pub const SETTINGS_DIR_NAME: &str = "DwmLock";
 pub const SETTINGS_FILE_NAME: &str = "dwmlock_settings.json";
+pub const POLICY_DIR_NAME: &str = "DwmLock";
+pub const POLICY_FILE_NAME: &str = "dwmlock_policy.json";
+pub const AUDIT_FILE_NAME: &str = "dwmlock_audit.jsonl";
+pub const AUDIT_MAX_BYTES: u64 = 1024 * 1024;
+pub const AUDIT_RETAINED_FILES: usize = 5;
@@ -0,0 +1,102 @@
+use chrono::Local;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    audit::{AuditEvent, AuditLog},
+    password::PasswordHash,
+};
+
+/// Secondary password that unlocks exactly like the real one but also fires
+/// `actions` without any visible sign on the lock screen.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct DuressConfig {
+    pub hash: PasswordHash,
+    #[serde(default = "default_duress_actions")]
+    pub actions: Vec<DuressAction>,
+}
+
+impl DuressConfig {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            hash: PasswordHash::new(secret),
+            actions: default_duress_actions(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DuressAction {
+    /// Starts a program detached from the lock screen; its exit status is not
+    /// awaited.
+    RunCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Appends the unlock time to a file another tool can watch for.
+    MarkerFile { path: PathBuf },
+    /// Appends a `duress_unlock` entry to the audit trail.
+    AuditEntry,
+}
+
+fn default_duress_actions() -> Vec<DuressAction> {
+    vec![DuressAction::AuditEntry]
+}
+
+/// Runs every action, returning one result per action so a failing command
+/// does not stop the marker file or audit entry.
+pub fn fire_duress_actions(actions: &[DuressAction], audit: &AuditLog) -> Vec<io::Result<()>> {
+    actions
+        .iter()
+        .map(|action| match action {
+            DuressAction::RunCommand { program, args } => spawn_detached(program, args),
+            DuressAction::MarkerFile { path } => write_marker(path),
+            DuressAction::AuditEntry => audit.append(AuditEvent::DuressUnlock),
+        })
+        .collect()
+}
+
+/// `fire_duress_actions` for the lock screen, which must not show anything:
+/// each failure becomes a `DuressActionFailed` entry in `audit` instead.
+pub fn run_duress_actions(actions: &[DuressAction], audit: &AuditLog) {
+    for err in fire_duress_actions(actions, audit)
+        .into_iter()
+        .filter_map(Result::err)
+    {
+        let _ = audit.append(AuditEvent::DuressActionFailed {
+            reason: err.to_string(),
+        });
+    }
+}
+
+fn spawn_detached(program: &str, args: &[String]) -> io::Result<()> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command.spawn().map(|_| ())
+}
+
+fn write_marker(path: &PathBuf) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", Local::now().to_rfc3339())
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Local};
+use rand::{Rng, rngs::OsRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{password::PasswordHash, secret::SecretString};
+
+const PASSWORD_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const PASSWORD_GROUPS: usize = 3;
+const GROUP_LEN: usize = 4;
+
+/// Temporary password for someone covering for the owner. It is accepted only
+/// between `valid_from` and `valid_until` (RFC 3339, either bound optional)
+/// and, when `remaining_uses` is set, only that many more times.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct GuestPassword {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    pub hash: PasswordHash,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_uses: Option<u32>,
+}
+
+impl GuestPassword {
+    pub fn new(
+        secret: &str,
+        label: &str,
+        valid_from: Option<DateTime<Local>>,
+        valid_until: Option<DateTime<Local>>,
+        remaining_uses: Option<u32>,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            hash: PasswordHash::new(secret),
+            valid_from: valid_from.map(|time| time.to_rfc3339()),
+            valid_until: valid_until.map(|time| time.to_rfc3339()),
+            remaining_uses,
+        }
+    }
+
+    /// Unparseable bounds fail closed: the entry is treated as expired.
+    pub fn is_expired_at(&self, now: DateTime<Local>) -> bool {
+        self.remaining_uses == Some(0)
+            || self
+                .valid_from
+                .as_deref()
+                .is_some_and(|from| parse(from).is_none())
+            || self
+                .valid_until
+                .as_deref()
+                .is_some_and(|until| parse(until).is_none_or(|until| now >= until))
+    }
+
+    pub fn is_active_at(&self, now: DateTime<Local>) -> bool {
+        !self.is_expired_at(now)
+            && self
+                .valid_from
+                .as_deref()
+                .and_then(parse)
+                .is_none_or(|from| now >= from)
+    }
+}
+
+/// Left behind by a guest unlock so the next lock screen can remind whoever
+/// re-locks when their access runs out. Cleared by an owner unlock.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct GuestSession {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+impl GuestSession {
+    pub fn for_guest(guest: &GuestPassword) -> Self {
+        Self {
+            label: guest.label.clone(),
+            expires_at: guest.valid_until.clone(),
+        }
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Local>> {
+        self.expires_at.as_deref().and_then(parse)
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Local>) -> bool {
+        self.expires_at
+            .as_deref()
+            .is_some_and(|until| parse(until).is_none_or(|until| now >= until))
+    }
+}
+
+/// Generates a readable random password, stores its hash and returns the
+/// plaintext so it can be handed to the guest.
+pub fn issue_guest_password(
+    guests: &mut Vec<GuestPassword>,
+    label: &str,
+    valid_until: Option<DateTime<Local>>,
+    remaining_uses: Option<u32>,
+) -> SecretString {
+    let secret = generate_password();
+    guests.push(GuestPassword::new(
+        secret.expose(),
+        label,
+        None,
+        valid_until,
+        remaining_uses,
+    ));
+    secret
+}
+
+/// Checks `input` against every guest password that is valid at `now`,
+/// spending one use of the match and returning its index. Every active entry
+/// is hashed so the time taken does not reveal which one matched.
+pub fn consume_guest_password(
+    guests: &mut [GuestPassword],
+    input: &str,
+    now: DateTime<Local>,
+) -> Option<usize> {
+    let mut matched = None;
+    for (index, guest) in guests.iter().enumerate() {
+        if guest.is_active_at(now) && guest.hash.verify(input) && matched.is_none() {
+            matched = Some(index);
+        }
+    }
+    record_guest_use(&mut guests[matched?]);
+    matched
+}
+
+/// Spends a use of the stored entry with the same hash as `used`, so a copy
+/// loaded fresh from disk can be updated without verifying the input again.
+pub fn record_guest_use_of(guests: &mut [GuestPassword], used: &PasswordHash) {
+    if let Some(guest) = guests.iter_mut().find(|guest| guest.hash == *used) {
+        record_guest_use(guest);
+    }
+}
+
+/// Drops entries that can never be accepted again. Returns `true` when
+/// anything was removed.
+pub fn prune_expired_guests(guests: &mut Vec<GuestPassword>, now: DateTime<Local>) -> bool {
+    let before = guests.len();
+    guests.retain(|guest| !guest.is_expired_at(now));
+    guests.len() != before
+}
+
+fn record_guest_use(guest: &mut GuestPassword) {
+    if let Some(uses) = &mut guest.remaining_uses {
+        *uses = uses.saturating_sub(1);
+    }
+}
+
+fn generate_password() -> SecretString {
+    let mut password = String::with_capacity(PASSWORD_GROUPS * (GROUP_LEN + 1));
+    for group in 0..PASSWORD_GROUPS {
+        if group > 0 {
+            password.push('-');
+        }
+        for _ in 0..GROUP_LEN {
+            let index = OsRng.gen_range(0..PASSWORD_ALPHABET.len());
+            password.push(PASSWORD_ALPHABET[index] as char);
+        }
+    }
+    SecretString::new(password)
+}
+
+fn parse(timestamp: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&Local))
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::secret::SecretString;
+
+/// Reserved up front so typing does not reallocate and leave stale copies of
+/// the input behind in freed memory.
+const INPUT_CAPACITY: usize = 256;
+
+/// Text typed into the lock prompt. `WM_CHAR` delivers UTF-16 code units, so
+/// characters outside the BMP arrive as a high and a low surrogate in two
+/// separate messages; the high half is held until its partner shows up.
+/// The buffer is wiped on `clear`, on `backspace` and when dropped.
+pub struct PasswordInput {
+    text: Zeroizing<String>,
+    pending_high_surrogate: Option<u16>,
+}
+
+impl Default for PasswordInput {
+    fn default() -> Self {
+        Self {
+            text: Zeroizing::new(String::with_capacity(INPUT_CAPACITY)),
+            pending_high_surrogate: None,
+        }
+    }
+}
+
+impl PasswordInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_utf16(&mut self, unit: u16) {
+        match unit {
+            0xD800..=0xDBFF => self.pending_high_surrogate = Some(unit),
+            0xDC00..=0xDFFF => {
+                if let Some(high) = self.pending_high_surrogate.take()
+                    && let Some(Ok(c)) = char::decode_utf16([high, unit]).next()
+                {
+                    self.push_char(c);
+                }
+            }
+            _ => {
+                self.pending_high_surrogate = None;
+                if let Some(c) = char::from_u32(unit as u32) {
+                    self.push_char(c);
+                }
+            }
+        }
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        if !c.is_control() {
+            self.text.push(c);
+        }
+    }
+
+    /// Removes the last user-perceived character, so "e" + combining acute
+    /// or a flag emoji disappears with one keystroke.
+    pub fn backspace(&mut self) {
+        self.pending_high_surrogate = None;
+        if let Some((index, _)) = self.text.grapheme_indices(true).next_back() {
+            let mut bytes = std::mem::take(&mut *self.text).into_bytes();
+            bytes[index..].zeroize();
+            bytes.truncate(index);
+            *self.text = String::from_utf8(bytes).expect("cut at a grapheme boundary");
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.text.zeroize();
+        self.pending_high_surrogate = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn grapheme_count(&self) -> usize {
+        self.text.graphemes(true).count()
+    }
+
+    /// The input in NFC form, matching how secrets are hashed.
+    pub fn normalized(&self) -> SecretString {
+        SecretString::new(normalize(&self.text))
+    }
+}
+
+impl fmt::Debug for PasswordInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordInput")
+            .field("graphemes", &self.grapheme_count())
+            .finish_non_exhaustive()
+    }
+}
+
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len() * 3);
+    normalized.extend(text.nfc());
+    normalized
+}
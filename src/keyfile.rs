@@ -0,0 +1,155 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::password::constant_time_eq;
+
+/// Files larger than this are skipped without reading them in full, so a
+/// broad pattern cannot make the probe read a whole disk image.
+pub const MAX_KEYFILE_BYTES: u64 = 1024 * 1024;
+
+/// Unlocks when a file whose SHA-256 matches `sha256` (hex) shows up at one of
+/// `paths`. Entries may use `*` and `?` in any component, and a leading `?:`
+/// tries every drive letter, so `?:\dwmlock.key` finds the stick wherever it
+/// is mounted.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct KeyfileConfig {
+    pub paths: Vec<String>,
+    pub sha256: String,
+}
+
+impl KeyfileConfig {
+    pub fn new(contents: &[u8], paths: Vec<String>) -> Self {
+        Self {
+            paths,
+            sha256: hex::encode(Sha256::digest(contents)),
+        }
+    }
+}
+
+/// The first candidate path whose contents hash to the configured value.
+/// The contents are wiped after hashing and are never returned or logged.
+pub fn find_matching_keyfile(config: &KeyfileConfig) -> Option<PathBuf> {
+    let expected = hex::decode(&config.sha256).ok()?;
+    config
+        .paths
+        .iter()
+        .flat_map(|pattern| expand_pattern(pattern))
+        .find(|path| keyfile_matches(path, &expected))
+}
+
+/// Whether `path` still holds the configured keyfile; used to check a match
+/// again right before unlocking.
+pub fn is_matching_keyfile(config: &KeyfileConfig, path: &Path) -> bool {
+    hex::decode(&config.sha256).is_ok_and(|expected| keyfile_matches(path, &expected))
+}
+
+fn keyfile_matches(path: &Path, expected: &[u8]) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    if !file
+        .metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.len() <= MAX_KEYFILE_BYTES)
+    {
+        return false;
+    }
+    let mut contents = Zeroizing::new(Vec::new());
+    if file
+        .take(MAX_KEYFILE_BYTES)
+        .read_to_end(&mut contents)
+        .is_err()
+    {
+        return false;
+    }
+    constant_time_eq(&Sha256::digest(&*contents), expected)
+}
+
+/// Existing paths matching `pattern`. Components without wildcards are taken
+/// as they are; the others are matched against the directory listing.
+fn expand_pattern(pattern: &str) -> Vec<PathBuf> {
+    let roots: Vec<String> = match pattern.strip_prefix("?:") {
+        Some(rest) => ('A'..='Z').map(|drive| format!("{drive}:{rest}")).collect(),
+        None => vec![pattern.to_string()],
+    };
+    roots
+        .iter()
+        .flat_map(|root| {
+            let mut candidates = vec![PathBuf::new()];
+            for component in Path::new(root).components() {
+                candidates = match component {
+                    Component::Normal(name) if has_wildcard(&name.to_string_lossy()) => {
+                        let name = name.to_string_lossy();
+                        candidates
+                            .iter()
+                            .flat_map(|dir| matching_entries(dir, &name))
+                            .collect()
+                    }
+                    other => candidates
+                        .into_iter()
+                        .map(|dir| dir.join(other.as_os_str()))
+                        .collect(),
+                };
+            }
+            candidates.into_iter().filter(|path| path.exists())
+        })
+        .collect()
+}
+
+fn matching_entries(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let listing = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let Ok(entries) = listing else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| wildcard_match(pattern, &entry.file_name().to_string_lossy()))
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    matches.sort();
+    matches
+}
+
+fn has_wildcard(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+/// `*` matches any run of characters and `?` exactly one. Case-insensitive,
+/// as Windows file names are.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
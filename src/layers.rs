@@ -0,0 +1,440 @@
+//! Resolves the effective `Settings` from several layers. From lowest to
+//! highest precedence: built-in defaults, the user's settings file, the
+//! selected profile, `DWMLOCK_*` environment variables, `--set key=value` command-line flags,
+//! and the machine-wide policy file. Policy values are locked: the settings
+//! dialog greys them out and saving never writes them to the user's file.
+//!
+//! Keys are dotted paths into the settings JSON, e.g. `blur_radius` or
+//! `password_policy.min_length`.
+
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, env, fmt, fs, io, path::PathBuf};
+
+use crate::{
+    cli::GlobalOptions,
+    config::{POLICY_DIR_NAME, POLICY_FILE_NAME},
+    profiles::select_profile,
+    settings::{Settings, SettingsError, load_settings, read_settings_from, settings_path},
+};
+
+pub const ENV_PREFIX: &str = "DWMLOCK_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigLayer {
+    Default,
+    User,
+    Profile,
+    Environment,
+    CommandLine,
+    MachinePolicy,
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigLayer::Default => "the built-in defaults",
+            ConfigLayer::User => "the user's settings file",
+            ConfigLayer::Profile => "the selected profile",
+            ConfigLayer::Environment => "the environment",
+            ConfigLayer::CommandLine => "the command line",
+            ConfigLayer::MachinePolicy => "the machine policy",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Override {
+    layer: ConfigLayer,
+    value: Value,
+    /// What the user's file holds at this key, written back on save.
+    user_value: Option<Value>,
+}
+
+/// Which keys of a resolved `Settings` come from a layer above the user's
+/// file. Empty for settings loaded straight from the file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SettingsLayers {
+    overrides: BTreeMap<String, Override>,
+    profile: Option<String>,
+}
+
+impl SettingsLayers {
+    /// The name of the profile applied on top of the user's file.
+    pub fn active_profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    fn covering(&self, key: &str) -> Option<&Override> {
+        self.overrides
+            .iter()
+            .filter(|(path, _)| is_within(key, path))
+            .max_by_key(|(path, _)| path.len())
+            .map(|(_, entry)| entry)
+    }
+
+    /// Whether the machine policy sets `key` or an object containing it.
+    pub fn is_locked(&self, key: &str) -> bool {
+        self.overrides.iter().any(|(path, entry)| {
+            entry.layer == ConfigLayer::MachinePolicy
+                && (is_within(key, path) || is_within(path, key))
+        })
+    }
+
+    pub fn locked_keys(&self) -> Vec<&str> {
+        self.overrides
+            .iter()
+            .filter(|(_, entry)| entry.layer == ConfigLayer::MachinePolicy)
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    /// Turns the effective settings JSON back into what belongs in the user's
+    /// file: locked keys get the user's own value back, and environment or
+    /// command-line overrides do too unless the value was changed since.
+    pub fn user_view(&self, mut value: Value) -> Value {
+        for (path, entry) in &self.overrides {
+            let edited = get_path(&value, path) != Some(&entry.value);
+            if entry.layer != ConfigLayer::MachinePolicy && edited {
+                continue;
+            }
+            match &entry.user_value {
+                Some(user_value) => set_path(&mut value, path, user_value.clone()),
+                None => remove_path(&mut value, path),
+            }
+        }
+        value
+    }
+}
+
+impl Settings {
+    /// The layer `key` was resolved from. Keys no layer overrides report
+    /// `User`, or `Default` when they still hold the built-in value.
+    pub fn value_origin(&self, key: &str) -> ConfigLayer {
+        if let Some(entry) = self.layers.covering(key) {
+            return entry.layer;
+        }
+        let current = serde_json::to_value(self).ok();
+        let default = serde_json::to_value(Settings::default()).ok();
+        let current = current.as_ref().and_then(|value| get_path(value, key));
+        let default = default.as_ref().and_then(|value| get_path(value, key));
+        if current == default {
+            ConfigLayer::Default
+        } else {
+            ConfigLayer::User
+        }
+    }
+
+    pub fn is_locked(&self, key: &str) -> bool {
+        self.layers.is_locked(key)
+    }
+}
+
+/// Applies the layers above the user's file to `user`, starting with the
+/// profile named `profile` (or `user.default_profile`). Each layer is checked
+/// on its own, so a bad value is reported against the layer that set it.
+pub fn resolve_settings(
+    user: Settings,
+    profile: Option<&str>,
+    policy: Option<Value>,
+    environment: Vec<(String, Value)>,
+    command_line: Vec<(String, Value)>,
+) -> Result<Settings, SettingsError> {
+    let user_value = serde_json::to_value(&user).map_err(|err| SettingsError::Layer {
+        layer: ConfigLayer::User,
+        message: err.to_string(),
+    })?;
+    let mut value = user_value.clone();
+    let mut layers = SettingsLayers::default();
+    let profile = match select_profile(&user, profile) {
+        Ok(Some((name, profile))) => {
+            layers.profile = Some(name.to_string());
+            profile.overrides()
+        }
+        Ok(None) => Vec::new(),
+        Err(message) => {
+            return Err(SettingsError::Layer {
+                layer: ConfigLayer::Profile,
+                message,
+            });
+        }
+    };
+    let policy = match policy {
+        Some(Value::Object(object)) => leaves(&object),
+        Some(_) => {
+            return Err(SettingsError::Layer {
+                layer: ConfigLayer::MachinePolicy,
+                message: "the policy file is not a JSON object".to_string(),
+            });
+        }
+        None => Vec::new(),
+    };
+    for (layer, overrides) in [
+        (ConfigLayer::Profile, profile),
+        (ConfigLayer::Environment, environment),
+        (ConfigLayer::CommandLine, command_line),
+        (ConfigLayer::MachinePolicy, policy),
+    ] {
+        if overrides.is_empty() {
+            continue;
+        }
+        for (path, override_value) in overrides {
+            set_path(&mut value, &path, override_value.clone());
+            layers.overrides.insert(
+                path.clone(),
+                Override {
+                    layer,
+                    value: override_value,
+                    user_value: get_path(&user_value, &path).cloned(),
+                },
+            );
+        }
+        serde_json::from_value::<Settings>(value.clone()).map_err(|err| SettingsError::Layer {
+            layer,
+            message: err.to_string(),
+        })?;
+    }
+    let mut settings: Settings =
+        serde_json::from_value(value).map_err(|err| SettingsError::Layer {
+            layer: ConfigLayer::User,
+            message: err.to_string(),
+        })?;
+    settings.layers = layers;
+    Ok(settings)
+}
+
+/// Loads the user's file and applies the profile and `--set` overrides from
+/// the command line, the machine policy and the process environment.
+pub fn load_layered_settings(global: &GlobalOptions) -> Result<Settings, SettingsError> {
+    layer_settings(load_settings()?, global)
+}
+
+/// `load_layered_settings` through `settings::read_settings_from`, so
+/// nothing is written back.
+pub fn read_layered_settings(global: &GlobalOptions) -> Result<Settings, SettingsError> {
+    layer_settings(read_settings_from(&settings_path())?, global)
+}
+
+fn layer_settings(user: Settings, global: &GlobalOptions) -> Result<Settings, SettingsError> {
+    let policy = read_machine_policy()?;
+    resolve_settings(
+        user,
+        global.profile.as_deref(),
+        policy,
+        environment_overrides(env::vars()),
+        global.overrides.clone(),
+    )
+}
+
+/// Field names holding secrets or hashes of secrets, wherever they appear
+/// (profiles carry their own `authenticators`). `settings get` and
+/// `settings list` show them as hidden.
+pub const SECRET_KEYS: &[&str] = &[
+    "authenticators",
+    "challenge",
+    "duress",
+    "guest_passwords",
+    "password_hash",
+    "recovery_codes",
+];
+pub const HIDDEN_VALUE: &str = "<hidden>";
+
+pub fn is_secret_key(key: &str) -> bool {
+    key.split('.').any(|part| SECRET_KEYS.contains(&part))
+}
+
+/// The value at `key` as JSON, with secrets replaced by `HIDDEN_VALUE`.
+/// `None` when the settings have nothing at `key`.
+pub fn get_setting(settings: &Settings, key: &str) -> Option<Value> {
+    let value = serde_json::to_value(settings).ok()?;
+    let found = get_path(&value, key)?;
+    if is_secret_key(key) {
+        return Some(Value::String(HIDDEN_VALUE.to_string()));
+    }
+    let mut found = found.clone();
+    redact(&mut found);
+    Some(found)
+}
+
+/// Every top-level setting with its value and origin, sorted by key.
+pub fn list_settings(settings: &Settings) -> Vec<(String, Value, ConfigLayer)> {
+    let Ok(Value::Object(object)) = serde_json::to_value(settings) else {
+        return Vec::new();
+    };
+    object
+        .keys()
+        .filter_map(|key| {
+            let value = get_setting(settings, key)?;
+            Some((key.clone(), value, settings.value_origin(key)))
+        })
+        .collect()
+}
+
+/// `user` with `value` stored at `key`. Fails when the value has the wrong
+/// type or `key` is not a setting, so typos do not vanish silently.
+pub fn set_setting(user: &Settings, key: &str, value: Value) -> Result<Settings, SettingsError> {
+    let invalid = |message: String| SettingsError::Layer {
+        layer: ConfigLayer::CommandLine,
+        message,
+    };
+    let mut edited = serde_json::to_value(user).map_err(|err| invalid(err.to_string()))?;
+    set_path(&mut edited, key, value.clone());
+    let settings: Settings =
+        serde_json::from_value(edited).map_err(|err| invalid(format!("{key}: {err}")))?;
+    let stored = serde_json::to_value(&settings).map_err(|err| invalid(err.to_string()))?;
+    if get_path(&stored, key) != Some(&value) {
+        return Err(invalid(format!("{key:?} is not a setting")));
+    }
+    Ok(settings)
+}
+
+/// `user` with `key` back at its built-in default, or, without a key, every
+/// setting except the password.
+pub fn reset_setting(user: &Settings, key: Option<&str>) -> Result<Settings, SettingsError> {
+    let Some(key) = key else {
+        return Ok(Settings {
+            password_hash: user.password_hash.clone(),
+            password_changed_at: user.password_changed_at.clone(),
+            ..Settings::default()
+        });
+    };
+    let invalid = |message: String| SettingsError::Layer {
+        layer: ConfigLayer::CommandLine,
+        message,
+    };
+    let default =
+        serde_json::to_value(Settings::default()).map_err(|err| invalid(err.to_string()))?;
+    let mut edited = serde_json::to_value(user).map_err(|err| invalid(err.to_string()))?;
+    if get_path(&edited, key).is_none() && get_path(&default, key).is_none() {
+        return Err(invalid(format!("{key:?} is not a setting")));
+    }
+    match get_path(&default, key) {
+        Some(value) => set_path(&mut edited, key, value.clone()),
+        None => remove_path(&mut edited, key),
+    }
+    serde_json::from_value(edited).map_err(|err| invalid(format!("{key}: {err}")))
+}
+
+/// Replaces every secret field inside `value` with `HIDDEN_VALUE`.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, inner) in object.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *inner = Value::String(HIDDEN_VALUE.to_string());
+                } else {
+                    redact(inner);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+pub fn machine_policy_path() -> PathBuf {
+    let base = env::var_os("PROGRAMDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"));
+    base.join(POLICY_DIR_NAME).join(POLICY_FILE_NAME)
+}
+
+fn read_machine_policy() -> Result<Option<Value>, SettingsError> {
+    match fs::read(machine_policy_path()) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| SettingsError::Layer {
+                layer: ConfigLayer::MachinePolicy,
+                message: err.to_string(),
+            }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(SettingsError::Layer {
+            layer: ConfigLayer::MachinePolicy,
+            message: err.to_string(),
+        }),
+    }
+}
+
+/// `DWMLOCK_BLUR_RADIUS=20` sets `blur_radius`; a double underscore steps
+/// into an object, as in `DWMLOCK_PASSWORD_POLICY__MIN_LENGTH`.
+pub fn environment_overrides(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Vec<(String, Value)> {
+    let mut overrides: Vec<(String, Value)> = vars
+        .into_iter()
+        .filter_map(|(name, raw)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            let path = key.to_lowercase().replace("__", ".");
+            (!path.is_empty()).then(|| (path, parse_override_value(&raw)))
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.0.cmp(&b.0));
+    overrides
+}
+
+/// JSON when it parses (numbers, booleans, arrays), a plain string otherwise.
+pub fn parse_override_value(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+fn leaves(object: &Map<String, Value>) -> Vec<(String, Value)> {
+    let mut found = Vec::new();
+    for (key, value) in object {
+        match value {
+            Value::Object(inner) if !inner.is_empty() => {
+                found.extend(
+                    leaves(inner)
+                        .into_iter()
+                        .map(|(path, leaf)| (format!("{key}.{path}"), leaf)),
+                );
+            }
+            _ => found.push((key.clone(), value.clone())),
+        }
+    }
+    found
+}
+
+/// `key` equals `path` or lies inside the object at `path`.
+fn is_within(key: &str, path: &str) -> bool {
+    key == path
+        || key
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |current, key| current.get(key))
+}
+
+fn set_path(value: &mut Value, path: &str, new_value: Value) {
+    let mut current = value;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        let object = current.as_object_mut().expect("just made an object");
+        if keys.peek().is_none() {
+            object.insert(key.to_string(), new_value);
+            return;
+        }
+        current = object
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+fn remove_path(value: &mut Value, path: &str) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (get_path_mut(value, parent), key),
+        None => (Some(value), path),
+    };
+    if let Some(Value::Object(object)) = parent {
+        object.remove(key);
+    }
+}
+
+fn get_path_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(value, |current, key| current.get_mut(key))
+}
@@ -1,10 +1,38 @@
+pub mod audit;
+pub mod auth;
+pub mod backoff;
 pub mod blur;
+pub mod bundle;
+#[cfg(windows)]
 pub mod capture;
+pub mod challenge;
+pub mod cli;
 pub mod config;
+pub mod duress;
+pub mod guest;
+pub mod input;
+#[cfg(windows)]
 pub mod keyboard;
+pub mod keyfile;
+pub mod layers;
+pub mod migrations;
+#[cfg(windows)]
 pub mod monitors;
+#[cfg(windows)]
 pub mod notifications;
+pub mod password;
+pub mod policy;
+pub mod profiles;
+pub mod recovery;
+pub mod reload;
+#[cfg(windows)]
 pub mod render;
+pub mod schema;
+pub mod secret;
 pub mod settings;
+pub mod shamir;
+#[cfg(windows)]
 pub mod state;
+pub mod token;
+#[cfg(windows)]
 pub mod ui;
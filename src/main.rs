@@ -1,64 +1,451 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
-#[cfg(not(windows))]
-compile_error!("dwmlock currently only targets Windows platforms.");
-
+use chrono::{Local, TimeDelta};
+#[cfg(windows)]
 use dwmlock::{
+    audit::{self, AuditEvent},
+    cli::EXIT_CANCELLED,
     keyboard::CtrlAltDeleteHook,
     notifications::dismiss_notifications,
-    settings::{Settings, load_settings, persist_settings},
     state::init_state,
     ui::{
         settings_dialog::show_settings_dialog,
         window::{build_app_state, confirm_lock, create_window_loop},
     },
 };
-use std::{env, process};
+use dwmlock::{
+    auth::{
+        shares::enroll_shares,
+        totp::{enroll_totp, unix_now},
+    },
+    bundle::{export_bundle, plan_import, read_bundle, write_bundle},
+    challenge::ChallengeConfig,
+    cli::{Command, EXIT_FAILURE, EXIT_USAGE, GlobalOptions, Invocation, parse_args},
+    guest::issue_guest_password,
+    keyfile::KeyfileConfig,
+    layers::{
+        ConfigLayer, get_setting, list_settings, load_layered_settings, machine_policy_path,
+        reset_setting, set_setting,
+    },
+    password::PasswordHash,
+    recovery::regenerate_recovery_codes,
+    schema::settings_schema_json,
+    secret::SecretString,
+    settings::{
+        Settings, SettingsError, check_settings_file, load_settings, persist_settings,
+        read_settings_from, settings_path,
+    },
+    token::{generate_signing_key, machine_name, sign_unlock_token, signing_key_from_hex},
+};
+#[cfg(not(windows))]
+use std::io::Result;
+use std::{
+    env, fs,
+    io::{self, BufRead},
+    path::Path,
+    process,
+};
+#[cfg(windows)]
 use windows::core::Result;
 
 fn main() {
     if let Err(err) = run() {
         eprintln!("dwmlock failed: {err:?}");
-        process::exit(1);
+        process::exit(EXIT_FAILURE);
     }
 }
 
 fn run() -> Result<()> {
-    unsafe {
-        let mut settings = load_settings();
-        if should_open_settings_ui(&settings) {
-            if show_settings_dialog(&mut settings)? {
-                persist_settings(&settings);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Invocation { command, global } = match parse_args(&args) {
+        Ok(invocation) => invocation,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(EXIT_USAGE);
+        }
+    };
+    match command {
+        Command::Help(text) => println!("{text}"),
+        Command::Schema => print!("{}", settings_schema_json()),
+        Command::Version => println!("dwmlock {}", env!("CARGO_PKG_VERSION")),
+        Command::ConfigCheck => run_check_config(),
+        Command::ConfigPath => {
+            println!("settings: {}", settings_path().display());
+            println!("policy:   {}", machine_policy_path().display());
+        }
+        Command::HashPassword { password } => run_hash_password(password),
+        Command::TokenKeygen => {
+            let (private_key, public_key) = generate_signing_key();
+            println!("private key: {}", private_key.expose());
+            println!("public key:  {public_key}");
+        }
+        Command::TokenSign {
+            key_file,
+            machine,
+            hours,
+        } => run_token_sign(&key_file, machine, hours),
+        Command::Respond { challenge } => run_respond_command(&challenge, &global),
+        Command::SettingsList => run_settings_list(&global),
+        Command::SettingsGet { key } => run_settings_get(&key, &global),
+        Command::SettingsSet { key, value } => {
+            run_settings_edit(&key, &global, |user| set_setting(user, &key, value))
+        }
+        Command::SettingsReset { key } => {
+            let target = key.as_deref().unwrap_or("");
+            run_settings_edit(target, &global, |user| reset_setting(user, key.as_deref()))
+        }
+        Command::SettingsExport { file } => run_settings_export(&file),
+        Command::SettingsImport { file, dry_run } => run_settings_import(&file, dry_run, &global),
+        Command::Lock { yes, open_settings } => unsafe {
+            run_lock(load_or_exit(&global), global, yes, open_settings)?
+        },
+        setup => run_setup_command(setup, load_or_exit(&global)),
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+unsafe fn run_lock(
+    mut settings: Settings,
+    global: GlobalOptions,
+    yes: bool,
+    open_settings: bool,
+) -> Result<()> {
+    if settings.open_settings_on_startup || open_settings {
+        show_settings_dialog(&mut settings)?;
+    }
+
+    if settings.lock_policy_violation(Local::now()).is_some() {
+        show_settings_dialog(&mut settings)?;
+        if let Some(violation) = settings.lock_policy_violation(Local::now()) {
+            eprintln!("dwmlock will not lock: {violation}");
+            process::exit(EXIT_FAILURE);
+        }
+    }
+
+    if settings.dismiss_notifications_on_startup {
+        dismiss_notifications();
+    }
+
+    if !yes && !confirm_lock() {
+        process::exit(EXIT_CANCELLED);
+    }
+
+    let mut initial_state = build_app_state(settings)?;
+    initial_state.global = global;
+    init_state(initial_state);
+    audit::record(AuditEvent::LockStarted);
+
+    let _ctrl_alt_delete_hook = CtrlAltDeleteHook::install()?;
+    create_window_loop()
+}
+
+/// The lock screen is Win32 only; every other command works anywhere.
+#[cfg(not(windows))]
+unsafe fn run_lock(
+    _settings: Settings,
+    _global: GlobalOptions,
+    _yes: bool,
+    _open_settings: bool,
+) -> Result<()> {
+    eprintln!("dwmlock can only lock the screen on Windows");
+    process::exit(EXIT_FAILURE);
+}
+
+/// The one-off setup options such as `--enroll-totp`, which change the
+/// settings file and print what the user has to keep.
+fn run_setup_command(command: Command, mut settings: Settings) {
+    match command {
+        Command::EnrollTotp => {
+            let account = env::var("USERNAME").unwrap_or_else(|_| "user".to_string());
+            let uri = enroll_totp(&mut settings, &account);
+            save_or_exit(&settings);
+            println!("{uri}");
+        }
+        Command::GenerateRecoveryCodes => {
+            let codes = regenerate_recovery_codes(&mut settings.recovery_codes);
+            save_or_exit(&settings);
+            for code in &codes {
+                println!("{}", code.expose());
+            }
+        }
+        Command::EnableChallenge => {
+            let config = ChallengeConfig::generate();
+            println!("{}", config.secret.expose());
+            settings.challenge = Some(config);
+            save_or_exit(&settings);
+        }
+        Command::GenerateShares { threshold, count } => {
+            let Some(shares) = enroll_shares(&mut settings, threshold, count) else {
+                eprintln!("cannot split into {count} shares with a threshold of {threshold}");
+                process::exit(EXIT_USAGE);
+            };
+            save_or_exit(&settings);
+            for share in &shares {
+                println!("{}", share.expose());
             }
         }
+        Command::SetKeyfile { keyfile, patterns } => {
+            let contents = match fs::read(&keyfile) {
+                Ok(contents) => zeroize::Zeroizing::new(contents),
+                Err(err) => {
+                    eprintln!("cannot read {keyfile}: {err}");
+                    process::exit(EXIT_FAILURE);
+                }
+            };
+            let paths = if patterns.is_empty() {
+                vec![keyfile]
+            } else {
+                patterns
+            };
+            settings.keyfile = Some(KeyfileConfig::new(&contents, paths));
+            save_or_exit(&settings);
+        }
+        Command::AddGuestPassword { label, hours, uses } => {
+            let valid_until = TimeDelta::try_minutes((hours * 60.0).round() as i64)
+                .and_then(|lifetime| Local::now().checked_add_signed(lifetime));
+            let Some(valid_until) = valid_until else {
+                eprintln!("{hours} hours is too long for a guest password");
+                process::exit(EXIT_USAGE);
+            };
+            let secret = issue_guest_password(
+                &mut settings.guest_passwords,
+                &label,
+                Some(valid_until),
+                uses,
+            );
+            save_or_exit(&settings);
+            println!("{}", secret.expose());
+            println!("valid until {}", valid_until.format("%Y-%m-%d %H:%M"));
+        }
+        other => unreachable!("{other:?} is not a setup command"),
+    }
+}
 
-        if settings.dismiss_notifications_on_startup {
-            dismiss_notifications();
+/// `dwmlock token sign` prints a token signed with the private key in
+/// `key_file`.
+fn run_token_sign(key_file: &str, machine: Option<String>, hours: u64) {
+    let key = fs::read_to_string(key_file)
+        .ok()
+        .and_then(|text| signing_key_from_hex(&text));
+    let Some(key) = key else {
+        eprintln!("{key_file}: not a private key");
+        process::exit(EXIT_FAILURE);
+    };
+    let Some(expires) = hours
+        .checked_mul(3600)
+        .and_then(|secs| unix_now().checked_add(secs))
+    else {
+        eprintln!("{hours} hours is too long for an unlock token");
+        process::exit(EXIT_USAGE);
+    };
+    let machine = machine.unwrap_or_else(machine_name);
+    println!("{}", sign_unlock_token(&key, &machine, expires));
+}
+
+/// `dwmlock respond <challenge>` prints the helpdesk response, using the
+/// shared secret from this machine's settings file.
+fn run_respond_command(challenge: &str, global: &GlobalOptions) {
+    let settings = load_or_exit(global);
+    match settings
+        .challenge
+        .as_ref()
+        .and_then(|config| config.response(challenge))
+    {
+        Some(response) => println!("{response}"),
+        None => {
+            eprintln!("no valid challenge secret is configured");
+            process::exit(EXIT_FAILURE);
         }
+    }
+}
 
-        if !confirm_lock() {
-            return Ok(());
+/// `dwmlock hash-password` prints the hash as JSON. Without an argument
+/// the password is read from the first line of standard input, which keeps
+/// it out of the shell history.
+fn run_hash_password(password: Option<SecretString>) {
+    let password = password.unwrap_or_else(|| {
+        let mut line = zeroize::Zeroizing::new(String::new());
+        if let Err(err) = io::stdin().lock().read_line(&mut line) {
+            eprintln!("cannot read the password: {err}");
+            process::exit(EXIT_FAILURE);
         }
+        SecretString::from(line.trim_end_matches(['\r', '\n']))
+    });
+    if password.expose().is_empty() {
+        eprintln!("the password must not be empty");
+        process::exit(EXIT_USAGE);
+    }
+    let hash = PasswordHash::new(password.expose());
+    match serde_json::to_string_pretty(&hash) {
+        Ok(json) => println!("{json}"),
+        Err(err) => {
+            eprintln!("cannot encode the hash: {err}");
+            process::exit(EXIT_FAILURE);
+        }
+    }
+}
 
-        let initial_state = build_app_state(&settings)?;
-        init_state(initial_state);
+fn run_settings_list(global: &GlobalOptions) {
+    let settings = load_or_exit(global);
+    for (key, value, origin) in list_settings(&settings) {
+        println!("{key} = {value}  ({origin})");
+    }
+}
 
-        let _ctrl_alt_delete_hook = CtrlAltDeleteHook::install()?;
-        create_window_loop()?;
+fn run_settings_get(key: &str, global: &GlobalOptions) {
+    let settings = load_or_exit(global);
+    match get_setting(&settings, key) {
+        Some(value) => println!("{value}"),
+        None => {
+            eprintln!("{key:?} is not set");
+            process::exit(EXIT_FAILURE);
+        }
     }
+}
 
-    Ok(())
+/// Edits the user's own file, never the layered result, so values from a
+/// profile, the environment or `--set` are not written back. An empty `key`
+/// means the whole file; keys pinned by the machine policy are refused.
+fn run_settings_edit(
+    key: &str,
+    global: &GlobalOptions,
+    edit: impl FnOnce(&Settings) -> std::result::Result<Settings, SettingsError>,
+) {
+    let layered = load_or_exit(global);
+    if !key.is_empty() && layered.is_locked(key) {
+        eprintln!("{key} is set by the machine policy and cannot be changed");
+        process::exit(EXIT_FAILURE);
+    }
+    match edit(&load_user_or_exit()) {
+        Ok(edited) => save_or_exit(&edited),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(EXIT_FAILURE);
+        }
+    }
+    if !key.is_empty() && layered.value_origin(key) > ConfigLayer::User {
+        println!(
+            "saved; {key} is currently overridden by {}",
+            layered.value_origin(key)
+        );
+    }
 }
 
-fn should_open_settings_ui(settings: &Settings) -> bool {
-    if settings.open_settings_on_startup {
-        return true;
+/// Exports the user's own file, so nothing from the policy, a profile
+/// selection or the environment ends up in the bundle.
+fn run_settings_export(file: &str) {
+    let user = load_user_or_exit();
+    if let Err(err) = write_bundle(Path::new(file), &export_bundle(&user)) {
+        eprintln!("{file}: {err}");
+        process::exit(EXIT_FAILURE);
     }
+    println!("exported to {file}");
+}
 
-    env::args().any(|arg| {
-        matches!(
-            arg.as_str(),
-            "--settings" | "--open-settings" | "--settings-on-startup"
-        )
-    })
+/// Lists what the bundle changes, then saves everything in one write unless
+/// `dry_run` is set. An invalid bundle changes nothing.
+fn run_settings_import(file: &str, dry_run: bool, global: &GlobalOptions) {
+    // A dry run must not touch the disk, so it skips the migrations and
+    // backups that `load_settings` may save.
+    let current = if dry_run {
+        read_settings_from(&settings_path()).unwrap_or_else(|err| {
+            eprintln!("{}", describe_settings_error(&err));
+            process::exit(EXIT_FAILURE);
+        })
+    } else {
+        load_user_or_exit()
+    };
+    let plan = read_bundle(Path::new(file)).and_then(|bundle| plan_import(&current, &bundle));
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("{file}: {err}");
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    let show = |value: &Option<serde_json::Value>| match value {
+        Some(value) => value.to_string(),
+        None => "(unset)".to_string(),
+    };
+    for change in &plan.changes {
+        println!(
+            "{}: {} -> {}",
+            change.key,
+            show(&change.before),
+            show(&change.after)
+        );
+    }
+    if plan.is_empty() {
+        println!("the bundle matches the current settings");
+        return;
+    }
+    if dry_run {
+        println!("dry run: nothing was saved");
+        return;
+    }
+    save_or_exit(&plan.settings);
+    let layered = load_or_exit(global);
+    for change in &plan.changes {
+        if layered.is_locked(&change.key) {
+            println!(
+                "{} is set by the machine policy, which still takes precedence",
+                change.key
+            );
+        }
+    }
+    println!("imported {} settings from {file}", plan.changes.len());
+}
+
+/// `dwmlock config check` validates the settings file without changing it
+/// and exits with status 1 if it would not load.
+fn run_check_config() {
+    let path = settings_path();
+    match check_settings_file(&path) {
+        Ok(()) => println!("{}: ok", path.display()),
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            process::exit(EXIT_FAILURE);
+        }
+    }
+}
+
+fn load_user_or_exit() -> Settings {
+    match load_settings() {
+        Ok(user) => user,
+        Err(err) => {
+            eprintln!("{}", describe_settings_error(&err));
+            process::exit(EXIT_FAILURE);
+        }
+    }
+}
+
+fn load_or_exit(global: &GlobalOptions) -> Settings {
+    match load_layered_settings(global) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("dwmlock will not start: {}", describe_settings_error(&err));
+            process::exit(EXIT_FAILURE);
+        }
+    }
+}
+
+/// Prefixes the error with the file it is about.
+fn describe_settings_error(err: &SettingsError) -> String {
+    match err {
+        SettingsError::Layer {
+            layer: ConfigLayer::MachinePolicy,
+            ..
+        } => format!("{}: {err}", machine_policy_path().display()),
+        SettingsError::Layer { .. } => err.to_string(),
+        _ => format!("{}: {err}", settings_path().display()),
+    }
+}
+
+/// Saves settings changed by a command, or exits with status 1.
+fn save_or_exit(settings: &Settings) {
+    if let Err(err) = persist_settings(settings) {
+        eprintln!("{}: {err}", settings_path().display());
+        process::exit(EXIT_FAILURE);
+    }
 }
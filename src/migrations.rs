@@ -0,0 +1,82 @@
+//! Upgrades settings files written by older releases. Each migration takes
+//! the JSON object from one `schema_version` to the next and runs before the
+//! object is deserialized into `Settings`, so fields can be renamed or
+//! restructured without serde aliases piling up on the struct.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades version `n` to `n + 1`. Files without a
+/// `schema_version` predate versioning and count as version 0.
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] = [migrate_v0_to_v1];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationError {
+    NotAnObject,
+    InvalidVersion,
+    /// Written by a newer release; rewriting it would drop whatever that
+    /// release added.
+    NewerVersion {
+        found: u64,
+        supported: u32,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotAnObject => f.write_str("the settings file is not a JSON object"),
+            MigrationError::InvalidVersion => {
+                write!(f, "`{SCHEMA_VERSION_KEY}` is not a non-negative integer")
+            }
+            MigrationError::NewerVersion { found, supported } => write!(
+                f,
+                "the settings file uses schema version {found}, but this build only understands up to {supported}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+pub fn schema_version(value: &Value) -> Result<u64, MigrationError> {
+    let object = value.as_object().ok_or(MigrationError::NotAnObject)?;
+    match object.get(SCHEMA_VERSION_KEY) {
+        None => Ok(0),
+        Some(version) => version.as_u64().ok_or(MigrationError::InvalidVersion),
+    }
+}
+
+/// Runs every migration between the file's version and the current one.
+/// Returns `true` when anything ran, so the caller knows to save the result.
+pub fn migrate_settings_value(value: &mut Value) -> Result<bool, MigrationError> {
+    let version = schema_version(value)?;
+    if version > u64::from(CURRENT_SCHEMA_VERSION) {
+        return Err(MigrationError::NewerVersion {
+            found: version,
+            supported: CURRENT_SCHEMA_VERSION,
+        });
+    }
+    let object = value.as_object_mut().ok_or(MigrationError::NotAnObject)?;
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(object);
+    }
+    object.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        Value::from(CURRENT_SCHEMA_VERSION),
+    );
+    Ok(version < u64::from(CURRENT_SCHEMA_VERSION))
+}
+
+/// v0 → v1: `show_settings_on_startup` became `open_settings_on_startup`.
+/// When both are present the newer name wins.
+fn migrate_v0_to_v1(object: &mut Map<String, Value>) {
+    if let Some(show) = object.remove("show_settings_on_startup") {
+        object.entry("open_settings_on_startup").or_insert(show);
+    }
+}
@@ -0,0 +1,92 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::{RngCore, rngs::OsRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use zeroize::Zeroizing;
+
+use crate::input::normalize;
+
+pub const HASH_ALGORITHM: &str = "argon2id";
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+const MEMORY_COST_KIB: u32 = 19 * 1024;
+const TIME_COST: u32 = 2;
+const PARALLELISM: u32 = 1;
+
+/// Salted Argon2id verifier for the unlock password. The parameters are stored
+/// next to the digest so older files keep verifying if the defaults change.
+/// Secrets are NFC-normalized before hashing, so composed and decomposed
+/// spellings of the same text verify alike.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PasswordHash {
+    pub algorithm: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+    pub hash: String,
+}
+
+impl PasswordHash {
+    pub fn new(secret: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let digest = derive(secret, &salt, MEMORY_COST_KIB, TIME_COST, PARALLELISM)
+            .expect("default argon2 parameters are valid");
+        Self {
+            algorithm: HASH_ALGORITHM.to_string(),
+            memory_kib: MEMORY_COST_KIB,
+            iterations: TIME_COST,
+            parallelism: PARALLELISM,
+            salt: hex::encode(salt),
+            hash: hex::encode(digest),
+        }
+    }
+
+    pub fn verify(&self, candidate: &str) -> bool {
+        if self.algorithm != HASH_ALGORITHM {
+            return false;
+        }
+        let (Ok(salt), Ok(expected)) = (hex::decode(&self.salt), hex::decode(&self.hash)) else {
+            return false;
+        };
+        match derive(
+            candidate,
+            &salt,
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+        ) {
+            Some(actual) => constant_time_eq(&actual, &expected),
+            None => false,
+        }
+    }
+}
+
+fn derive(
+    secret: &str,
+    salt: &[u8],
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Option<[u8; HASH_LEN]> {
+    let params = Params::new(memory_kib, iterations, parallelism, Some(HASH_LEN)).ok()?;
+    let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let normalized = Zeroizing::new(normalize(secret));
+    let mut output = [0u8; HASH_LEN];
+    argon
+        .hash_password_into(normalized.as_bytes(), salt, &mut output)
+        .ok()?;
+    Some(output)
+}
+
+pub fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    left.iter()
+        .zip(right.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
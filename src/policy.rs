@@ -0,0 +1,138 @@
+use chrono::{DateTime, Local, TimeDelta};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+use zeroize::Zeroizing;
+
+use crate::{config::DEFAULT_PASSWORD, input::normalize};
+
+/// Rules a new unlock password has to meet. Length is counted in
+/// user-perceived characters, matching the mask on the lock screen. The
+/// deny-list is compared case-insensitively against the whole password.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Fewest characters, counted as user-perceived characters.
+    pub min_length: usize,
+    /// How many of lowercase, uppercase, digits and other characters must
+    /// appear at least once.
+    #[schemars(range(max = 4))]
+    pub min_character_classes: usize,
+    /// Passwords refused outright, compared case-insensitively.
+    pub deny_list: Vec<String>,
+    /// Days before the password must be changed; unset means never.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u32>,
+    /// Lets the machine lock while the password is still the built-in default.
+    pub allow_default_password: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            min_character_classes: 1,
+            deny_list: vec![
+                DEFAULT_PASSWORD.to_string(),
+                "password".to_string(),
+                "12345678".to_string(),
+                "dwmlock".to_string(),
+            ],
+            max_age_days: None,
+            allow_default_password: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooShort { min_length: usize },
+    TooFewCharacterClasses { required: usize },
+    DenyListed,
+    DefaultPassword,
+    Expired { max_age_days: u32 },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooShort { min_length } => {
+                write!(
+                    f,
+                    "The password must be at least {min_length} characters long."
+                )
+            }
+            PolicyViolation::TooFewCharacterClasses { required } => write!(
+                f,
+                "Use at least {required} of: lowercase, uppercase, digits, symbols."
+            ),
+            PolicyViolation::DenyListed => f.write_str("That password is too common."),
+            PolicyViolation::DefaultPassword => {
+                f.write_str("The default password is still set; choose a new one.")
+            }
+            PolicyViolation::Expired { max_age_days } => write!(
+                f,
+                "The password is older than {max_age_days} days; choose a new one."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+impl PasswordPolicy {
+    pub fn check_password(&self, candidate: &str) -> Result<(), PolicyViolation> {
+        let normalized = Zeroizing::new(normalize(candidate));
+        if normalized.graphemes(true).count() < self.min_length {
+            return Err(PolicyViolation::TooShort {
+                min_length: self.min_length,
+            });
+        }
+        if character_classes(&normalized) < self.min_character_classes {
+            return Err(PolicyViolation::TooFewCharacterClasses {
+                required: self.min_character_classes,
+            });
+        }
+        let lowered = Zeroizing::new(normalized.to_lowercase());
+        if self
+            .deny_list
+            .iter()
+            .any(|denied| normalize(denied).to_lowercase() == *lowered)
+        {
+            return Err(PolicyViolation::DenyListed);
+        }
+        Ok(())
+    }
+
+    /// A password without a recorded change time is never considered expired,
+    /// and neither is one whose expiry lies beyond the range of a date.
+    pub fn is_expired(&self, changed_at: Option<&str>, now: DateTime<Local>) -> bool {
+        let (Some(max_age_days), Some(changed_at)) = (self.max_age_days, changed_at) else {
+            return false;
+        };
+        let expires = DateTime::parse_from_rfc3339(changed_at)
+            .ok()
+            .and_then(|changed| {
+                changed.checked_add_signed(TimeDelta::try_days(i64::from(max_age_days))?)
+            });
+        expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+fn character_classes(text: &str) -> usize {
+    let mut seen = [false; 4];
+    for c in text.chars() {
+        let class = if c.is_lowercase() {
+            0
+        } else if c.is_uppercase() {
+            1
+        } else if c.is_numeric() {
+            2
+        } else {
+            3
+        };
+        seen[class] = true;
+    }
+    seen.iter().filter(|present| **present).count()
+}
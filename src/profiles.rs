@@ -0,0 +1,68 @@
+//! Named profiles such as "overnight" or "presentation" that override part of
+//! the base settings. A profile is applied as its own layer between the
+//! user's file and the environment, so saving keeps the base settings intact
+//! and the machine policy still wins.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    auth::AuthenticatorConfig,
+    config::{MAX_BLUR_RADIUS, MIN_BLUR_RADIUS},
+    layers::resolve_settings,
+    settings::{MonitorBlankingMode, Settings, SettingsError},
+};
+
+/// Fields left as `None` keep the base value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(default)]
+pub struct SettingsProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blur_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = MIN_BLUR_RADIUS, max = MAX_BLUR_RADIUS))]
+    pub blur_radius: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monitor_mode: Option<MonitorBlankingMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_monitors: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authenticators: Option<Vec<AuthenticatorConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dismiss_notifications_on_startup: Option<bool>,
+}
+
+impl SettingsProfile {
+    /// The keys this profile sets, with their values.
+    pub fn overrides(&self) -> Vec<(String, Value)> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(object)) => object.into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The profile to apply: `requested` if given, otherwise `default_profile`.
+/// Returns the name together with the profile, or `None` for the base
+/// settings alone.
+pub fn select_profile<'a>(
+    settings: &'a Settings,
+    requested: Option<&'a str>,
+) -> Result<Option<(&'a str, &'a SettingsProfile)>, String> {
+    let Some(name) = requested.or(settings.default_profile.as_deref()) else {
+        return Ok(None);
+    };
+    match settings.profiles.get(name) {
+        Some(profile) => Ok(Some((name, profile))),
+        None => Err(format!("there is no profile named {name:?}")),
+    }
+}
+
+/// `base` with the selected profile applied on top. Pure: the other layers
+/// are left to `layers::load_layered_settings`.
+pub fn resolve_profile(base: Settings, requested: Option<&str>) -> Result<Settings, SettingsError> {
+    resolve_settings(base, requested, None, Vec::new(), Vec::new())
+}
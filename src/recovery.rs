@@ -0,0 +1,112 @@
+use chrono::Local;
+use rand::{Rng, RngCore, rngs::OsRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{password::constant_time_eq, secret::SecretString};
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_GROUPS: usize = 4;
+const GROUP_LEN: usize = 4;
+const SALT_LEN: usize = 16;
+
+/// One single-use emergency code. Only a salted SHA-256 digest is stored; the
+/// codes carry 80 random bits, so a slow hash would add nothing but delay.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct RecoveryCode {
+    pub salt: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used_at: Option<String>,
+}
+
+impl RecoveryCode {
+    fn new(code: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            salt: hex::encode(salt),
+            hash: hex::encode(digest(&salt, code)),
+            used_at: None,
+        }
+    }
+
+    fn matches(&self, canonical: &str) -> bool {
+        let (Ok(salt), Ok(expected)) = (hex::decode(&self.salt), hex::decode(&self.hash)) else {
+            return false;
+        };
+        constant_time_eq(&digest(&salt, canonical), &expected)
+    }
+}
+
+/// Replaces the whole set with freshly generated codes, invalidating every
+/// previous one, and returns the plaintext codes for the user to write down.
+pub fn regenerate_recovery_codes(codes: &mut Vec<RecoveryCode>) -> Vec<SecretString> {
+    let plaintext: Vec<SecretString> = (0..RECOVERY_CODE_COUNT).map(|_| generate_code()).collect();
+    *codes = plaintext
+        .iter()
+        .map(|code| RecoveryCode::new(&canonicalize(code.expose())))
+        .collect();
+    plaintext
+}
+
+/// Marks the matching unused code as consumed. Every stored code is checked so
+/// the time taken does not depend on which one matched.
+pub fn consume_recovery_code(codes: &mut [RecoveryCode], input: &str) -> bool {
+    let canonical = canonicalize(input);
+    let mut matched = None;
+    for (index, code) in codes.iter().enumerate() {
+        if code.matches(&canonical) && code.used_at.is_none() && matched.is_none() {
+            matched = Some(index);
+        }
+    }
+    match matched {
+        Some(index) => {
+            codes[index].used_at = Some(Local::now().to_rfc3339());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn remaining_recovery_codes(codes: &[RecoveryCode]) -> usize {
+    codes.iter().filter(|code| code.used_at.is_none()).count()
+}
+
+/// Cheap shape check so ordinary passwords are not hashed against every code.
+pub fn looks_like_recovery_code(input: &str) -> bool {
+    let canonical = canonicalize(input);
+    canonical.len() == CODE_GROUPS * GROUP_LEN
+        && canonical.bytes().all(|byte| CODE_ALPHABET.contains(&byte))
+}
+
+fn generate_code() -> SecretString {
+    let mut code = String::with_capacity(CODE_GROUPS * (GROUP_LEN + 1));
+    for group in 0..CODE_GROUPS {
+        if group > 0 {
+            code.push('-');
+        }
+        for _ in 0..GROUP_LEN {
+            let index = OsRng.gen_range(0..CODE_ALPHABET.len());
+            code.push(CODE_ALPHABET[index] as char);
+        }
+    }
+    SecretString::new(code)
+}
+
+fn canonicalize(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn digest(salt: &[u8], canonical: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(canonical.as_bytes());
+    hasher.finalize().into()
+}
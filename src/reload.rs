@@ -0,0 +1,82 @@
+//! Applies edits to the settings file made while the screen is locked. Only
+//! fields that change how the lock screen looks are taken over at once;
+//! everything else, such as the password, the factor chain or the unlock
+//! alternatives, stays as it was when the lock started and takes effect at
+//! the next lock.
+
+use std::{fs, path::Path, time::SystemTime};
+
+use crate::settings::{Settings, changed_fields};
+
+/// Top-level settings a running lock screen may pick up from disk.
+pub const RELOADABLE_FIELDS: &[&str] = &[
+    "blur_enabled",
+    "blur_radius",
+    "disable_monitors",
+    "dismiss_notifications_on_startup",
+    "lock_message",
+    "monitor_mode",
+    "open_settings_on_startup",
+    "show_settings_button",
+    "text_on_all_monitors",
+];
+
+/// Modification time and size, cheap enough to compare on every timer tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+pub fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some(FileStamp {
+        modified: meta.modified().ok(),
+        len: meta.len(),
+    })
+}
+
+/// The changed fields, split into those applied now and those held back
+/// until the next lock.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReloadPlan {
+    pub applied: Vec<String>,
+    pub deferred: Vec<String>,
+}
+
+impl ReloadPlan {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.deferred.is_empty()
+    }
+}
+
+pub fn plan_reload(current: &Settings, incoming: &Settings) -> ReloadPlan {
+    let (applied, deferred) = changed_fields(current, incoming)
+        .into_iter()
+        .partition(|field| RELOADABLE_FIELDS.contains(&field.as_str()));
+    ReloadPlan { applied, deferred }
+}
+
+/// `current` with the planned fields taken from `incoming`. Layer
+/// information stays that of `current`, like the deferred fields.
+pub fn apply_reload(current: &Settings, incoming: &Settings, plan: &ReloadPlan) -> Settings {
+    let (Ok(serde_json::Value::Object(mut merged)), Ok(serde_json::Value::Object(new))) = (
+        serde_json::to_value(current),
+        serde_json::to_value(incoming),
+    ) else {
+        return current.clone();
+    };
+    for field in &plan.applied {
+        match new.get(field) {
+            Some(value) => merged.insert(field.clone(), value.clone()),
+            None => merged.remove(field),
+        };
+    }
+    match serde_json::from_value::<Settings>(serde_json::Value::Object(merged)) {
+        Ok(mut settings) => {
+            settings.layers = current.layers.clone();
+            settings
+        }
+        Err(_) => current.clone(),
+    }
+}
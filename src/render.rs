@@ -1,7 +1,7 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
 use chrono::Local;
-use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
+use std::{ffi::OsStr, os::windows::ffi::OsStrExt, time::Instant};
 use windows::{
     Win32::{
         Foundation::{COLORREF, RECT},
@@ -18,7 +18,7 @@ use windows::{
 
 use crate::{
     config::WARNING_MESSAGE,
-    state::{AppState, warning_active},
+    state::{AppState, active_challenge, retry_countdown, warning_active},
 };
 
 const PRIMARY_FONT: &str = "Segoe UI Variable Display";
@@ -88,8 +88,27 @@ unsafe fn draw_normal_content(hdc: HDC, rect: RECT, state: &AppState) {
     let now = Local::now();
     let time_text = now.format("%H:%M:%S").to_string();
     let date_text = now.format("%A, %B %d %Y").to_string();
-    let tagline = "Windows input is locked; type the password and press Enter.";
-    let hint_text = "Use Backspace to correct mistakes. Ctrl+Alt+Delete is suppressed.";
+    let tagline = state
+        .settings
+        .lock_message
+        .as_deref()
+        .unwrap_or("Windows input is locked; type the password and press Enter.");
+    let guest_expiry = state
+        .settings
+        .guest_session
+        .as_ref()
+        .and_then(|session| session.expires_at());
+    let hint_text = match (challenge_text(state), guest_expiry) {
+        (Some(challenge), _) => challenge,
+        (None, Some(expires_at)) => format!(
+            "Guest access expires at {}.",
+            expires_at.format("%H:%M on %B %d")
+        ),
+        (None, None) if state.settings.show_settings_button => {
+            "Type the password before clicking Settings. Ctrl+Alt+Delete is suppressed.".to_string()
+        }
+        (None, None) => "Ctrl+Alt+Delete is suppressed.".to_string(),
+    };
 
     let mut tag_rect = rect;
     tag_rect.left += spacing;
@@ -154,14 +173,16 @@ unsafe fn draw_normal_content(hdc: HDC, rect: RECT, state: &AppState) {
     draw_text_with_font(
         hdc,
         &hint_rect,
-        hint_text,
+        &hint_text,
         scaled(18, scale),
         FW_MEDIUM.0 as i32,
         COLORREF(0x00C7D2EE),
         PRIMARY_FONT,
     );
-    let button_rect = settings_button_rect(state);
-    draw_settings_button(hdc, button_rect, scale);
+    if state.settings.show_settings_button {
+        let button_rect = settings_button_rect(state);
+        draw_settings_button(hdc, button_rect, scale);
+    }
 }
 
 unsafe fn draw_warning_content(hdc: HDC, rect: RECT, state: &AppState) {
@@ -170,7 +191,18 @@ unsafe fn draw_warning_content(hdc: HDC, rect: RECT, state: &AppState) {
     let now = Local::now();
     let top_message = format!("{}!", WARNING_MESSAGE);
     let time_text = now.format("%H:%M:%S").to_string();
-    let hint_text = "Hands off the keyboard and mouse until the warning clears.";
+    let hint_text = match retry_countdown(state) {
+        Some(left) => {
+            let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+            format!(
+                "Too many failed attempts. Try again in {}:{:02}.",
+                secs / 60,
+                secs % 60
+            )
+        }
+        None => "Hands off the keyboard and mouse until the warning clears.".to_string(),
+    };
+    let challenge_text = challenge_text(state);
 
     let mut alert_rect = rect;
     alert_rect.left += spacing;
@@ -215,12 +247,39 @@ unsafe fn draw_warning_content(hdc: HDC, rect: RECT, state: &AppState) {
     draw_text_with_font(
         hdc,
         &hint_rect,
-        hint_text,
+        &hint_text,
         scaled(22, scale),
         FW_NORMAL.0 as i32,
         COLORREF(0x00F0C674),
         PRIMARY_FONT,
     );
+
+    if let Some(challenge_text) = challenge_text {
+        let mut challenge_rect = hint_rect;
+        challenge_rect.top = hint_rect.bottom + spacing / 2;
+        challenge_rect.bottom = challenge_rect.top + scaled(30, scale);
+        draw_text_with_font(
+            hdc,
+            &challenge_rect,
+            &challenge_text,
+            scaled(22, scale),
+            FW_MEDIUM.0 as i32,
+            COLORREF(0x00FFFFFF),
+            MONO_FONT,
+        );
+    }
+}
+
+fn challenge_text(state: &AppState) -> Option<String> {
+    let expiry = state.settings.challenge.as_ref()?.expiry();
+    let challenge = active_challenge(state)?;
+    let secs = challenge.remaining(expiry, Instant::now())?.as_secs();
+    Some(format!(
+        "Helpdesk challenge: {} (valid for {}:{:02}). Type the response to unlock.",
+        challenge.code,
+        secs / 60,
+        secs % 60
+    ))
 }
 
 pub fn settings_button_rect(state: &AppState) -> RECT {
@@ -268,9 +327,18 @@ unsafe fn draw_password_field(hdc: HDC, rect: &RECT, state: &AppState, font_size
     let masked = if state.input.is_empty() {
         "…".to_string()
     } else {
-        "*".repeat(state.input.chars().count())
+        "*".repeat(state.input.grapheme_count())
+    };
+    let prompt = state.auth.prompt();
+    let password_text = if state.auth.stage_count() > 1 {
+        format!(
+            "{prompt} ({}/{}): {masked}",
+            state.auth.stage_index() + 1,
+            state.auth.stage_count()
+        )
+    } else {
+        format!("{prompt}: {masked}")
     };
-    let password_text = format!("Password: {masked}");
     draw_text_with_font(
         hdc,
         rect,
@@ -0,0 +1,23 @@
+//! JSON Schema for `dwmlock_settings.json`, derived from the `Settings` types
+//! so provisioning scripts and editors can catch typos before a machine ever
+//! loads the file. A copy is committed at `SCHEMA_PATH` and the test suite
+//! fails when it no longer matches the types.
+
+use serde_json::Value;
+
+use crate::settings::Settings;
+
+/// The published copy, relative to the repository root.
+pub const SCHEMA_PATH: &str = "docs/dwmlock_settings.schema.json";
+
+pub fn settings_schema() -> Value {
+    schemars::schema_for!(Settings).to_value()
+}
+
+/// `settings_schema` formatted as the published file.
+pub fn settings_schema_json() -> String {
+    let mut text =
+        serde_json::to_string_pretty(&settings_schema()).expect("a schema serializes to JSON");
+    text.push('\n');
+    text
+}
@@ -1,14 +1,47 @@
+use chrono::{DateTime, Local};
 use dirs::config_dir;
+use rand::{RngCore, rngs::OsRng};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
 
-use crate::config::{DEFAULT_BLUR_RADIUS, DEFAULT_PASSWORD, SETTINGS_DIR_NAME, SETTINGS_FILE_NAME};
+use crate::{
+    auth::{AuthenticatorConfig, totp::unix_now},
+    backoff::BackoffPolicy,
+    challenge::ChallengeConfig,
+    config::{
+        DEFAULT_BLUR_RADIUS, DEFAULT_PASSWORD, MAX_BLUR_RADIUS, MIN_BLUR_RADIUS, SETTINGS_DIR_NAME,
+        SETTINGS_FILE_NAME,
+    },
+    duress::DuressConfig,
+    guest::{GuestPassword, GuestSession, prune_expired_guests},
+    keyfile::KeyfileConfig,
+    layers::{ConfigLayer, SettingsLayers},
+    migrations::{CURRENT_SCHEMA_VERSION, MigrationError, migrate_settings_value, schema_version},
+    password::PasswordHash,
+    policy::{PasswordPolicy, PolicyViolation},
+    profiles::SettingsProfile,
+    recovery::RecoveryCode,
+    secret::SecretString,
+    token::{UnlockTokenConfig, UsedNonce, prune_used_nonces},
+};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+/// Which monitors are covered by a blank overlay while locked.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum MonitorBlankingMode {
+    /// No monitor is blanked.
     None,
+    /// Every monitor is blanked.
     All,
+    /// Only the monitors listed in `disable_monitors` are blanked.
     Custom,
 }
 
@@ -18,30 +51,117 @@ impl Default for MonitorBlankingMode {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The contents of `dwmlock_settings.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "dwmlock settings")]
 pub struct Settings {
-    #[serde(default = "default_password")]
-    pub password: String,
+    /// Format version of the file; older files are upgraded when loaded.
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
+    /// Plaintext password from files written before hashing was introduced.
+    /// Only read so `migrate_legacy_password` can replace it; never written back.
+    #[serde(default, rename = "password", skip_serializing)]
+    #[schemars(
+        description = "Deprecated plaintext password; replaced by `password_hash` when the file is loaded."
+    )]
+    pub legacy_password: Option<SecretString>,
+    /// Argon2id hash of the unlock password; `dwmlock hash-password` prints one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<PasswordHash>,
+    /// RFC 3339 time of the last password change, for `max_age_days`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<String>,
+    /// Rules a new password has to meet.
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
+    /// Factors that must all succeed, in order, to unlock.
+    #[serde(default = "default_authenticators")]
+    pub authenticators: Vec<AuthenticatorConfig>,
+    /// Delays and lockout after failed attempts.
+    #[serde(default)]
+    pub backoff: BackoffPolicy,
+    /// Hashed one-time codes from `--generate-recovery-codes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery_codes: Vec<RecoveryCode>,
+    /// A second password that unlocks and silently raises an alarm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duress: Option<DuressConfig>,
+    /// Temporary passwords from `--add-guest-password`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guest_passwords: Vec<GuestPassword>,
+    /// The guest access in use since the last guest unlock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guest_session: Option<GuestSession>,
+    /// Public key that signs administrator unlock tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_token: Option<UnlockTokenConfig>,
+    /// Unlock tokens already used, kept until they expire.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub used_token_nonces: Vec<UsedNonce>,
+    /// A file whose presence unlocks the screen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<KeyfileConfig>,
+    /// Helpdesk challenge–response after repeated failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<ChallengeConfig>,
+    /// Device names such as `DISPLAY2` blanked in `custom` mode.
     #[serde(default = "default_disable_monitors")]
     pub disable_monitors: Vec<String>,
+    /// Which monitors are blanked; `custom` uses `disable_monitors`.
     #[serde(default)]
     pub monitor_mode: MonitorBlankingMode,
-    #[serde(default, alias = "show_settings_on_startup")]
+    /// Opens the settings dialog before every lock.
+    #[serde(default)]
     pub open_settings_on_startup: bool,
+    /// Clears pending toast notifications before locking.
     #[serde(default = "default_dismiss_notifications")]
     pub dismiss_notifications_on_startup: bool,
+    /// Strength of the desktop blur.
     #[serde(default = "default_blur_radius")]
+    #[schemars(range(min = MIN_BLUR_RADIUS, max = MAX_BLUR_RADIUS))]
     pub blur_radius: usize,
+    /// Shows the blurred desktop instead of a plain background.
     #[serde(default = "default_blur_enabled")]
     pub blur_enabled: bool,
+    /// Draws the lock screen text on blanked monitors as well.
     #[serde(default = "default_text_on_all_monitors")]
     pub text_on_all_monitors: bool,
+    /// Shown on the lock screen instead of the standard tagline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_message: Option<String>,
+    /// Named partial settings selected with `--profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, SettingsProfile>,
+    /// Profile applied when no `--profile` flag is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Draws the Settings button on the lock screen. Policy can turn it off so
+    /// users cannot change settings while locked.
+    #[serde(default = "default_show_settings_button")]
+    pub show_settings_button: bool,
+    /// Filled in by `layers::resolve_settings`; never part of the file.
+    #[serde(skip)]
+    pub layers: SettingsLayers,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            password: default_password(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            legacy_password: None,
+            password_hash: None,
+            password_changed_at: None,
+            password_policy: PasswordPolicy::default(),
+            authenticators: default_authenticators(),
+            backoff: BackoffPolicy::default(),
+            recovery_codes: Vec::new(),
+            duress: None,
+            guest_passwords: Vec::new(),
+            guest_session: None,
+            unlock_token: None,
+            used_token_nonces: Vec::new(),
+            keyfile: None,
+            challenge: None,
             disable_monitors: default_disable_monitors(),
             monitor_mode: MonitorBlankingMode::default(),
             open_settings_on_startup: false,
@@ -49,47 +169,337 @@ impl Default for Settings {
             blur_radius: default_blur_radius(),
             blur_enabled: default_blur_enabled(),
             text_on_all_monitors: default_text_on_all_monitors(),
+            lock_message: None,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            show_settings_button: default_show_settings_button(),
+            layers: SettingsLayers::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn set_password(&mut self, secret: &str) {
+        self.legacy_password = None;
+        self.password_hash = Some(PasswordHash::new(secret));
+        self.password_changed_at = Some(Local::now().to_rfc3339());
+    }
+
+    /// `set_password`, but only for a password that meets `password_policy`.
+    pub fn change_password(&mut self, secret: &str) -> Result<(), PolicyViolation> {
+        self.password_policy.check_password(secret)?;
+        self.set_password(secret);
+        Ok(())
+    }
+
+    /// Replaces a plaintext password (or a missing one) with a salted hash.
+    /// Returns `true` when the settings changed and should be persisted.
+    pub fn migrate_legacy_password(&mut self) -> bool {
+        let legacy = self.legacy_password.take();
+        if self.password_hash.is_some() {
+            return legacy.is_some();
+        }
+        let secret = legacy
+            .filter(|value| !value.expose().trim().is_empty())
+            .unwrap_or_else(|| SecretString::new(default_password()));
+        self.set_password(secret.expose());
+        true
+    }
+
+    /// Keeps any configured duress actions and only swaps the password.
+    pub fn set_duress_password(&mut self, secret: &str) {
+        match &mut self.duress {
+            Some(duress) => duress.hash = PasswordHash::new(secret),
+            None => self.duress = Some(DuressConfig::new(secret)),
+        }
+    }
+
+    /// Drops guest passwords and a guest session that have run out. Returns
+    /// `true` when the settings changed and should be persisted.
+    pub fn prune_guest_access(&mut self, now: DateTime<Local>) -> bool {
+        let pruned = prune_expired_guests(&mut self.guest_passwords, now);
+        let session_expired = self
+            .guest_session
+            .as_ref()
+            .is_some_and(|session| session.is_expired_at(now));
+        if session_expired {
+            self.guest_session = None;
         }
+        pruned || session_expired
     }
+
+    /// Why the machine should not be locked with the current password: it is
+    /// still the built-in default (unless the policy allows that) or it has
+    /// outlived `max_age_days`.
+    pub fn lock_policy_violation(&self, now: DateTime<Local>) -> Option<PolicyViolation> {
+        let policy = &self.password_policy;
+        if !policy.allow_default_password && self.password_verifier().verify(DEFAULT_PASSWORD) {
+            return Some(PolicyViolation::DefaultPassword);
+        }
+        if policy.is_expired(self.password_changed_at.as_deref(), now) {
+            return policy
+                .max_age_days
+                .map(|max_age_days| PolicyViolation::Expired { max_age_days });
+        }
+        None
+    }
+
+    pub fn password_verifier(&self) -> PasswordHash {
+        self.password_hash
+            .clone()
+            .unwrap_or_else(|| PasswordHash::new(&default_password()))
+    }
+}
+
+/// Top-level keys whose serialized value differs between the two settings.
+pub fn changed_fields(before: &Settings, after: &Settings) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return Vec::new();
+    };
+    let mut fields: Vec<String> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .chain(old.keys().filter(|key| !new.contains_key(*key)).cloned())
+        .collect();
+    fields.sort();
+    fields
 }
 
-pub fn load_settings() -> Settings {
-    let path = settings_path();
-    let mut settings = if let Ok(bytes) = fs::read(&path) {
-        serde_json::from_slice(&bytes).unwrap_or_else(|_| ensure_default(&path))
+#[derive(Debug)]
+pub enum SettingsError {
+    Read(io::Error),
+    Write(io::Error),
+    /// The file is not valid JSON or does not match `Settings`. `line` and
+    /// `column` are 1-based; both are 0 when serde could not point at a spot.
+    /// `backup` is the timestamped copy kept next to the original.
+    Syntax {
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+        backup: Option<PathBuf>,
+    },
+    Schema(MigrationError),
+    /// The machine policy, environment or command line could not be applied.
+    Layer {
+        layer: ConfigLayer,
+        message: String,
+    },
+}
+
+impl SettingsError {
+    fn syntax(source: serde_json::Error) -> Self {
+        SettingsError::Syntax {
+            line: source.line(),
+            column: source.column(),
+            source,
+            backup: None,
+        }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Read(err) => write!(f, "the settings file cannot be read: {err}"),
+            SettingsError::Write(err) => write!(f, "the settings file cannot be written: {err}"),
+            SettingsError::Syntax { source, backup, .. } => {
+                write!(f, "the settings file is invalid: {source}")?;
+                match backup {
+                    Some(backup) => write!(f, " (a copy was kept as {})", backup.display()),
+                    None => Ok(()),
+                }
+            }
+            SettingsError::Schema(err) => err.fmt(f),
+            SettingsError::Layer { layer, message } => {
+                write!(f, "{layer} has an invalid setting: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Parses a settings file, running schema migrations first. The flag is
+/// `true` when the file came from an older schema and should be saved back.
+pub fn parse_settings(bytes: &[u8]) -> Result<(Settings, bool), SettingsError> {
+    let mut value: serde_json::Value =
+        serde_json::from_slice(bytes).map_err(SettingsError::syntax)?;
+    let migrated = migrate_settings_value(&mut value).map_err(SettingsError::Schema)?;
+    // Deserializing the original text keeps line and column in type errors;
+    // after a migration the text no longer matches the value.
+    let mut settings: Settings = if migrated {
+        serde_json::from_value(value)
     } else {
-        ensure_default(&path)
+        serde_json::from_slice(bytes)
+    }
+    .map_err(SettingsError::syntax)?;
+    settings.backoff = settings.backoff.clamped();
+    Ok((settings, migrated))
+}
+
+/// Validates the file at `path` without writing anything. A missing file is
+/// fine, since the defaults would be used.
+pub fn check_settings_file(path: &Path) -> Result<(), SettingsError> {
+    match fs::read(path) {
+        Ok(bytes) => parse_settings(&bytes).map(|_| ()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(SettingsError::Read(err)),
+    }
+}
+
+pub fn load_settings() -> Result<Settings, SettingsError> {
+    load_settings_from(&settings_path())
+}
+
+/// `load_settings_from` without any writes, for the reload poll.
+pub fn read_settings_from(path: &Path) -> Result<Settings, SettingsError> {
+    let mut settings = match fs::read(path) {
+        Ok(bytes) => parse_settings(&bytes)?.0,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Settings::default(),
+        Err(err) => return Err(SettingsError::Read(err)),
+    };
+    settings.migrate_legacy_password();
+    settings.prune_guest_access(Local::now());
+    prune_used_nonces(&mut settings.used_token_nonces, unix_now());
+    Ok(settings)
+}
+
+/// Loads, upgrades and tidies the settings at `path`, writing defaults when
+/// there is no file yet. A file that does not parse is never replaced: it is
+/// copied to a timestamped `.bak` and the error goes back to the caller.
+pub fn load_settings_from(path: &Path) -> Result<Settings, SettingsError> {
+    let (mut settings, upgraded) = match fs::read(path) {
+        Ok(bytes) => match parse_settings(&bytes) {
+            Ok(parsed) => parsed,
+            Err(SettingsError::Syntax {
+                line,
+                column,
+                source,
+                ..
+            }) => {
+                return Err(SettingsError::Syntax {
+                    line,
+                    column,
+                    source,
+                    backup: backup_settings_file(path, Local::now()).ok(),
+                });
+            }
+            Err(err) => return Err(err),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (ensure_default(path), false),
+        Err(err) => return Err(SettingsError::Read(err)),
     };
-    if settings.password.trim().is_empty() {
-        settings.password = default_password();
+    let migrated = settings.migrate_legacy_password();
+    let pruned = settings.prune_guest_access(Local::now());
+    let forgotten = prune_used_nonces(&mut settings.used_token_nonces, unix_now());
+    if (upgraded || migrated || pruned || forgotten)
+        && let Err(err) = persist_settings_to(path, &settings)
+    {
+        eprintln!("settings not saved: {err}");
     }
-    settings
+    Ok(settings)
+}
+
+/// Copies `path` to `<file name>.<timestamp>.bak` beside it.
+fn backup_settings_file(path: &Path, now: DateTime<Local>) -> io::Result<PathBuf> {
+    let backup = sibling_path(path, &format!(".{}.bak", now.format("%Y%m%d-%H%M%S")));
+    fs::copy(path, &backup)?;
+    Ok(backup)
 }
 
-fn ensure_default(path: &PathBuf) -> Settings {
+fn ensure_default(path: &Path) -> Settings {
     let settings = Settings::default();
-    save_settings(path, &settings);
+    if let Err(err) = persist_settings_to(path, &settings) {
+        eprintln!("default settings not saved: {err}");
+    }
     settings
 }
 
-fn save_settings(path: &PathBuf, settings: &Settings) {
+pub fn persist_settings(settings: &Settings) -> Result<(), SettingsError> {
+    persist_settings_to(&settings_path(), settings)
+}
+
+/// Saves `settings` to `path` so that a crash leaves either the old or the
+/// new file, never a truncated one. The file being replaced is kept as
+/// `<file name>.prev`. A file from a newer schema is not overwritten. Values
+/// that came from the policy, environment or command line are left out.
+pub fn persist_settings_to(path: &Path, settings: &Settings) -> Result<(), SettingsError> {
+    if let Some(found) = newer_schema_version(path) {
+        return Err(SettingsError::Schema(MigrationError::NewerVersion {
+            found,
+            supported: CURRENT_SCHEMA_VERSION,
+        }));
+    }
+    let value = serde_json::to_value(settings).map_err(|err| SettingsError::Write(err.into()))?;
+    let json = serde_json::to_vec_pretty(&settings.layers.user_view(value))
+        .map_err(|err| SettingsError::Write(err.into()))?;
+    write_atomically(path, &json).map_err(SettingsError::Write)
+}
+
+/// Writes and syncs a temporary file in the same directory, then renames it
+/// over `path`; the rename replaces the target in one step. The temporary
+/// name is unique per call so two writers never share it. Refreshing the
+/// `.prev` backup is best effort: a failure there is reported but does not
+/// stop the save.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
     if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+        fs::create_dir_all(parent)?;
     }
-    if let Ok(json) = serde_json::to_vec_pretty(settings) {
-        let _ = fs::write(path, json);
+    let temp = sibling_path(
+        path,
+        &format!(".{}.{:08x}.tmp", process::id(), OsRng.next_u32()),
+    );
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        });
+    let replaced = written.and_then(|()| {
+        if path.exists()
+            && let Err(err) = fs::copy(path, sibling_path(path, ".prev"))
+        {
+            eprintln!("warning: settings backup not updated: {err}");
+        }
+        fs::rename(&temp, path)
+    });
+    if replaced.is_err() {
+        let _ = fs::remove_file(&temp);
     }
+    replaced
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn newer_schema_version(path: &Path) -> Option<u64> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+        .and_then(|value| schema_version(&value).ok())
+        .filter(|version| *version > u64::from(CURRENT_SCHEMA_VERSION))
 }
 
-pub fn persist_settings(settings: &Settings) {
-    let path = settings_path();
-    save_settings(&path, settings);
+fn current_schema_version() -> u32 {
+    CURRENT_SCHEMA_VERSION
 }
 
 fn default_password() -> String {
     DEFAULT_PASSWORD.to_string()
 }
 
+fn default_authenticators() -> Vec<AuthenticatorConfig> {
+    vec![AuthenticatorConfig::Password]
+}
+
 fn default_disable_monitors() -> Vec<String> {
     vec!["DISPLAY2".to_string()]
 }
@@ -110,6 +520,10 @@ fn default_text_on_all_monitors() -> bool {
     false
 }
 
+fn default_show_settings_button() -> bool {
+    true
+}
+
 pub fn settings_path() -> PathBuf {
     let mut base = config_dir()
         .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
@@ -0,0 +1,125 @@
+//! Shamir secret sharing over GF(2^8): any `threshold` of the generated
+//! shares rebuild the secret, fewer reveal nothing about it.
+
+use rand::{RngCore, rngs::OsRng};
+use std::fmt;
+use zeroize::Zeroizing;
+
+use crate::{
+    auth::totp::{base32_decode, base32_encode},
+    secret::SecretString,
+};
+
+/// One point on each byte's polynomial. `index` is the x coordinate and is
+/// never zero, since the secret itself sits at x = 0.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    pub index: u8,
+    pub data: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// Text form `<index>-<base32 data>`, short enough to read out or type.
+    pub fn encode(&self) -> SecretString {
+        SecretString::new(format!("{}-{}", self.index, base32_encode(&self.data)))
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let (index, data) = text.trim().split_once('-')?;
+        let index: u8 = index.parse().ok().filter(|index| *index != 0)?;
+        let data = base32_decode(data).filter(|data| !data.is_empty())?;
+        Some(Self {
+            index,
+            data: Zeroizing::new(data),
+        })
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which recombine
+/// it. Returns `None` unless `1 <= threshold <= count`.
+pub fn split_secret(secret: &[u8], threshold: u8, count: u8) -> Option<Vec<Share>> {
+    if threshold == 0 || threshold > count {
+        return None;
+    }
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|index| Share {
+            index,
+            data: Zeroizing::new(Vec::with_capacity(secret.len())),
+        })
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for &byte in secret {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for share in &mut shares {
+            share.data.push(evaluate(&coefficients, share.index));
+        }
+    }
+    Some(shares)
+}
+
+/// Rebuilds the secret from distinct shares of equal length. With fewer
+/// shares than the threshold the result is a wrong but well-formed secret,
+/// so callers check it against a stored hash.
+pub fn combine_shares(shares: &[Share]) -> Option<Zeroizing<Vec<u8>>> {
+    let len = shares.first()?.data.len();
+    for (position, share) in shares.iter().enumerate() {
+        if share.index == 0
+            || share.data.len() != len
+            || shares[..position]
+                .iter()
+                .any(|other| other.index == share.index)
+        {
+            return None;
+        }
+    }
+    let mut secret = Zeroizing::new(vec![0u8; len]);
+    for (position, share) in shares.iter().enumerate() {
+        let mut basis = 1u8;
+        for (other_position, other) in shares.iter().enumerate() {
+            if other_position != position {
+                basis = gf_mul(basis, gf_div(other.index, other.index ^ share.index));
+            }
+        }
+        for (byte, &value) in secret.iter_mut().zip(share.data.iter()) {
+            *byte ^= gf_mul(value, basis);
+        }
+    }
+    Some(secret)
+}
+
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |acc, &coefficient| gf_mul(acc, x) ^ coefficient)
+}
+
+/// Multiplication modulo the AES polynomial, without data-dependent branches.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the multiplicative inverse of b in GF(2^8).
+    let mut inverse = 1u8;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
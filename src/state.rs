@@ -1,24 +1,67 @@
 use once_cell::sync::OnceCell;
-use std::{sync::Mutex, time::Instant};
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 use windows::{Win32::Foundation::HWND, Win32::Graphics::Gdi::BITMAPINFO};
 
-use crate::{config::WARNING_DURATION, settings::Settings};
+use crate::{
+    audit::{self, AuditEvent},
+    auth::AuthChain,
+    backoff::AttemptTracker,
+    challenge::{Challenge, challenge_due},
+    cli::GlobalOptions,
+    config::WARNING_DURATION,
+    input::PasswordInput,
+    password::PasswordHash,
+    reload::FileStamp,
+    settings::Settings,
+};
 
 pub static APP_STATE: OnceCell<Mutex<AppState>> = OnceCell::new();
 
-#[derive(Debug)]
 pub struct AppState {
     pub width: i32,
     pub height: i32,
     pub pixels: Vec<u8>,
     pub bitmap_info: BITMAPINFO,
-    pub password: String,
-    pub input: String,
+    pub verifier: PasswordHash,
+    pub auth: AuthChain,
+    pub attempts: AttemptTracker,
+    pub locked_since: Instant,
+    pub input: PasswordInput,
     pub warning_since: Option<Instant>,
+    pub challenge: Option<Challenge>,
     pub settings: Settings,
+    /// The settings file as last seen, to notice edits while locked.
+    pub settings_stamp: Option<FileStamp>,
+    /// `--profile` and `--set` from the command line, re-applied on reload.
+    pub global: GlobalOptions,
     pub monitor_windows: Vec<HWND>,
 }
 
+impl fmt::Debug for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppState")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixels", &format_args!("[{} bytes]", self.pixels.len()))
+            .field("verifier", &"<redacted>")
+            .field("input", &self.input)
+            .field("auth", &self.auth)
+            .field("attempts", &self.attempts)
+            .field("locked_since", &self.locked_since)
+            .field("warning_since", &self.warning_since)
+            .field("challenge", &self.challenge)
+            .field("settings", &self.settings)
+            .field("settings_stamp", &self.settings_stamp)
+            .field("global", &self.global)
+            .field("monitor_windows", &self.monitor_windows)
+            .finish_non_exhaustive()
+    }
+}
+
 pub fn init_state(state: AppState) {
     APP_STATE
         .set(Mutex::new(state))
@@ -41,9 +84,49 @@ pub fn arm_warning(state: &mut AppState) {
     state.warning_since = Some(Instant::now());
 }
 
-pub fn warning_active(state: &AppState) -> bool {
+/// Shared bookkeeping for any rejected credential, whether it came from the
+/// unlock prompt or the Settings button re-authentication.
+pub fn register_failed_attempt(state: &mut AppState) {
+    audit::record(AuditEvent::FailedAttempt {
+        input_length: state.input.grapheme_count(),
+    });
+    state.input.clear();
+    let now = Instant::now();
+    state.attempts.record_failure(now);
+    if let Some(config) = &state.settings.challenge
+        && challenge_due(
+            config,
+            state.attempts.failures(),
+            state.challenge.as_ref(),
+            now,
+        )
+    {
+        state.challenge = Some(Challenge::new(now));
+    }
+    arm_warning(state);
+}
+
+/// The helpdesk challenge currently on screen, if it has not expired.
+pub fn active_challenge(state: &AppState) -> Option<&Challenge> {
+    let expiry = state.settings.challenge.as_ref()?.expiry();
     state
-        .warning_since
-        .map(|instant| instant.elapsed() < WARNING_DURATION)
-        .unwrap_or(false)
+        .challenge
+        .as_ref()
+        .filter(|challenge| !challenge.is_expired(expiry, Instant::now()))
+}
+
+pub fn input_blocked(state: &AppState) -> bool {
+    retry_countdown(state).is_some()
+}
+
+pub fn retry_countdown(state: &AppState) -> Option<Duration> {
+    state.attempts.remaining(Instant::now())
+}
+
+pub fn warning_active(state: &AppState) -> bool {
+    input_blocked(state)
+        || state
+            .warning_since
+            .map(|instant| instant.elapsed() < WARNING_DURATION)
+            .unwrap_or(false)
 }
@@ -0,0 +1,226 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{RngCore, rngs::OsRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    auth::totp::{base32_decode, base32_encode},
+    secret::SecretString,
+    settings::settings_path,
+};
+
+/// Prepended to every signed message so a token signature cannot be reused
+/// for anything else the same key might sign.
+const TOKEN_CONTEXT: &[u8] = b"dwmlock-unlock-token-v1";
+pub const TOKEN_FILE_EXTENSION: &str = "token";
+const TOKEN_DIR_NAME: &str = "tokens";
+const NONCE_LEN: usize = 8;
+const SIGNATURE_LEN: usize = 64;
+
+/// Public half of the administrator's signing key, hex encoded, plus the
+/// directory watched for token files (next to the settings file by default).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct UnlockTokenConfig {
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+}
+
+impl UnlockTokenConfig {
+    pub fn directory(&self) -> PathBuf {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| settings_path().with_file_name(TOKEN_DIR_NAME))
+    }
+
+    fn verifying_key(&self) -> Option<VerifyingKey> {
+        let bytes: [u8; 32] = hex::decode(&self.public_key).ok()?.try_into().ok()?;
+        VerifyingKey::from_bytes(&bytes).ok()
+    }
+}
+
+/// A nonce that already unlocked this machine. Kept until the token it came
+/// from expires, after which the expiry check alone rejects it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct UsedNonce {
+    pub nonce: String,
+    pub expires: u64,
+}
+
+/// The signed claims: which machine may be unlocked, until when (Unix
+/// seconds), and a random nonce that makes every token single-use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnlockToken {
+    pub machine: String,
+    pub expires: u64,
+    pub nonce: String,
+}
+
+impl UnlockToken {
+    /// Text form: `<machine>.<expires>.<nonce>.<signature>`, the signature in
+    /// unpadded base32 so the whole token can be typed at the prompt.
+    pub fn sign(&self, key: &SigningKey) -> String {
+        let signature = key.sign(&self.signed_message());
+        format!(
+            "{}.{}.{}.{}",
+            self.machine,
+            self.expires,
+            self.nonce,
+            base32_encode(&signature.to_bytes())
+        )
+    }
+
+    fn parse(text: &str) -> Option<(Self, Signature)> {
+        let mut parts = text.trim().rsplitn(4, '.');
+        let signature = parts.next()?;
+        let nonce = parts.next()?;
+        let expires = parts.next()?.parse().ok()?;
+        let machine = parts.next().filter(|machine| !machine.is_empty())?;
+        let bytes: [u8; SIGNATURE_LEN] = base32_decode(signature)?.try_into().ok()?;
+        let token = Self {
+            machine: machine.to_string(),
+            expires,
+            nonce: nonce.to_ascii_lowercase(),
+        };
+        Some((token, Signature::from_bytes(&bytes)))
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        let mut message = TOKEN_CONTEXT.to_vec();
+        for field in [
+            self.machine.as_str(),
+            &self.expires.to_string(),
+            &self.nonce,
+        ] {
+            message.push(b'\n');
+            message.extend_from_slice(field.as_bytes());
+        }
+        message
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    NotConfigured,
+    Malformed,
+    BadSignature,
+    WrongMachine,
+    Expired,
+    Replayed,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenError::NotConfigured => "no token public key is configured",
+            TokenError::Malformed => "the token is malformed",
+            TokenError::BadSignature => "the token signature is invalid",
+            TokenError::WrongMachine => "the token is for a different machine",
+            TokenError::Expired => "the token has expired",
+            TokenError::Replayed => "the token has already been used",
+        })
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Checks the signature, machine name, expiry and nonce of `text`. On success
+/// the nonce is added to `used` so the same token is refused next time.
+pub fn verify_unlock_token(
+    text: &str,
+    config: &UnlockTokenConfig,
+    machine: &str,
+    now_unix: u64,
+    used: &mut Vec<UsedNonce>,
+) -> Result<UnlockToken, TokenError> {
+    let key = config.verifying_key().ok_or(TokenError::NotConfigured)?;
+    let (token, signature) = UnlockToken::parse(text).ok_or(TokenError::Malformed)?;
+    key.verify(&token.signed_message(), &signature)
+        .map_err(|_| TokenError::BadSignature)?;
+    if !token.machine.eq_ignore_ascii_case(machine) {
+        return Err(TokenError::WrongMachine);
+    }
+    if now_unix >= token.expires {
+        return Err(TokenError::Expired);
+    }
+    if used.iter().any(|entry| entry.nonce == token.nonce) {
+        return Err(TokenError::Replayed);
+    }
+    used.push(UsedNonce {
+        nonce: token.nonce.clone(),
+        expires: token.expires,
+    });
+    Ok(token)
+}
+
+/// Forgets nonces whose tokens have expired. Returns `true` when anything was
+/// removed.
+pub fn prune_used_nonces(used: &mut Vec<UsedNonce>, now_unix: u64) -> bool {
+    let before = used.len();
+    used.retain(|entry| entry.expires > now_unix);
+    used.len() != before
+}
+
+/// Issues a token for `machine` with a random nonce, valid until `expires`
+/// (Unix seconds).
+pub fn sign_unlock_token(key: &SigningKey, machine: &str, expires: u64) -> String {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    UnlockToken {
+        machine: machine.to_string(),
+        expires,
+        nonce: hex::encode(nonce),
+    }
+    .sign(key)
+}
+
+/// A fresh key pair as hex: the private half for the administrator, the
+/// public half for `Settings::unlock_token`.
+pub fn generate_signing_key() -> (SecretString, String) {
+    let key = SigningKey::generate(&mut OsRng);
+    (
+        SecretString::new(hex::encode(key.to_bytes())),
+        hex::encode(key.verifying_key().to_bytes()),
+    )
+}
+
+pub fn signing_key_from_hex(text: &str) -> Option<SigningKey> {
+    let bytes: [u8; 32] = hex::decode(text.trim()).ok()?.try_into().ok()?;
+    Some(SigningKey::from_bytes(&bytes))
+}
+
+/// Cheap shape check so ordinary passwords skip signature verification.
+pub fn looks_like_unlock_token(input: &str) -> bool {
+    UnlockToken::parse(input).is_some()
+}
+
+/// Reads and deletes every `*.token` file in `directory`, so a dropped token
+/// is only ever looked at once whether or not it verifies.
+pub fn take_token_files(directory: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == TOKEN_FILE_EXTENSION)
+        })
+        .filter_map(|path| {
+            let text = fs::read_to_string(&path).ok();
+            let _ = fs::remove_file(&path);
+            text
+        })
+        .collect()
+}
+
+pub fn machine_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
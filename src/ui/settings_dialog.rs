@@ -1,5 +1,6 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
+use chrono::Local;
 use std::{ffi::OsStr, os::windows::ffi::OsStrExt, sync::Once};
 
 use windows::{
@@ -10,8 +11,8 @@ use windows::{
         UI::WindowsAndMessaging::{
             BM_GETCHECK, BM_SETCHECK, BN_CLICKED, CB_ADDSTRING, CB_GETCURSEL, CB_SETCURSEL,
             CBN_SELCHANGE, CBS_DROPDOWNLIST, CBS_HASSTRINGS, CREATESTRUCTW, CreateWindowExW,
-            DefWindowProcW, DestroyWindow, DispatchMessageW, GWL_STYLE, GWLP_USERDATA, GetMessageW,
-            GetSystemMetrics, GetWindowLongPtrW, GetWindowRect, GetWindowTextW, HMENU,
+            DefWindowProcW, DestroyWindow, DispatchMessageW, ES_PASSWORD, GWL_STYLE, GWLP_USERDATA,
+            GetMessageW, GetSystemMetrics, GetWindowLongPtrW, GetWindowRect, GetWindowTextW, HMENU,
             HWND_TOPMOST, IDC_ARROW, LB_ADDSTRING, LB_DELETESTRING, LB_GETCURSEL, LB_RESETCONTENT,
             LoadCursorW, MSG, PostQuitMessage, RegisterClassW, SM_CXSCREEN, SM_CYSCREEN, SW_SHOW,
             SWP_NOSIZE, SendMessageW, SetWindowLongPtrW, SetWindowPos, SetWindowTextW, ShowWindow,
@@ -23,14 +24,18 @@ use windows::{
     core::{PCWSTR, Result, w},
 };
 
+use zeroize::Zeroizing;
+
 use crate::{
+    config::{MAX_BLUR_RADIUS, MIN_BLUR_RADIUS},
     monitors::available_monitor_names,
-    settings::{MonitorBlankingMode, Settings},
+    secret::SecretString,
+    settings::{MonitorBlankingMode, Settings, persist_settings},
 };
 
 const SETTINGS_CLASS_NAME: PCWSTR = w!("DwmLockSettingsWindow");
 const SETTINGS_WIDTH: i32 = 520;
-const SETTINGS_HEIGHT: i32 = 460;
+const SETTINGS_HEIGHT: i32 = 584;
 
 const ID_MONITOR_MODE_COMBO: isize = 1000;
 const ID_PASSWORD_EDIT: isize = 1001;
@@ -45,6 +50,8 @@ const ID_BLUR_CHECKBOX: isize = 1009;
 const ID_APPLY_BUTTON: isize = 1010;
 const ID_CLOSE_BUTTON: isize = 1011;
 const ID_TEXT_ON_MONITORS: isize = 1012;
+const ID_DURESS_EDIT: isize = 1013;
+const ID_PROFILE_COMBO: isize = 1014;
 const BST_CHECKED_STATE: usize = 1;
 const BST_UNCHECKED_STATE: usize = 0;
 const MONITOR_MODE_OPTIONS: &[(MonitorBlankingMode, &str); 3] = &[
@@ -53,6 +60,8 @@ const MONITOR_MODE_OPTIONS: &[(MonitorBlankingMode, &str); 3] = &[
     (MonitorBlankingMode::Custom, "Choose specific monitors"),
 ];
 
+const BASE_PROFILE_LABEL: &str = "No profile (base settings)";
+
 static SETTINGS_CLASS: Once = Once::new();
 
 pub unsafe fn show_settings_dialog(settings: &mut Settings) -> Result<bool> {
@@ -152,11 +161,16 @@ struct SettingsDialogState {
     monitor_names: Vec<String>,
     selected_monitors: Vec<String>,
     password_edit: Option<HWND>,
+    password_error: Option<HWND>,
+    save_error: Option<HWND>,
+    duress_edit: Option<HWND>,
     blur_edit: Option<HWND>,
     blur_checkbox: Option<HWND>,
     show_checkbox: Option<HWND>,
     dismiss_checkbox: Option<HWND>,
     monitor_mode_combo: Option<HWND>,
+    profile_combo: Option<HWND>,
+    profile_names: Vec<String>,
     monitor_combo: Option<HWND>,
     monitor_list: Option<HWND>,
     monitor_add_button: Option<HWND>,
@@ -175,11 +189,16 @@ impl SettingsDialogState {
             monitor_names,
             selected_monitors: settings.disable_monitors.clone(),
             password_edit: None,
+            password_error: None,
+            save_error: None,
+            duress_edit: None,
             blur_edit: None,
             blur_checkbox: None,
             show_checkbox: None,
             dismiss_checkbox: None,
             monitor_mode_combo: None,
+            profile_combo: None,
+            profile_names: settings.profiles.keys().cloned().collect(),
             monitor_combo: None,
             monitor_list: None,
             monitor_add_button: None,
@@ -198,17 +217,29 @@ impl SettingsDialogState {
         let content_width = SETTINGS_WIDTH - (left * 2);
         self.password_edit = Some(create_labeled_edit(
             hwnd,
-            "Password",
+            "New password (leave blank to keep the current one)",
             left,
             &mut layout_y,
             content_width,
             ID_PASSWORD_EDIT,
+            WINDOW_STYLE(ES_PASSWORD as u32),
         ));
-
-        if let Some(edit) = self.password_edit {
-            let current = self.current_password();
-            set_edit_text(edit, &current);
+        self.password_error = Some(create_label(hwnd, "", left, layout_y - 2, content_width));
+        if let Some(violation) = (*self.settings).lock_policy_violation(Local::now()) {
+            self.show_password_error(&violation.to_string());
         }
+        layout_y += 24;
+
+        layout_y += 10;
+        self.duress_edit = Some(create_labeled_edit(
+            hwnd,
+            "Duress password (optional, leave blank to keep)",
+            left,
+            &mut layout_y,
+            content_width,
+            ID_DURESS_EDIT,
+            WINDOW_STYLE(ES_PASSWORD as u32),
+        ));
 
         layout_y += 10;
         self.blur_edit = Some(create_labeled_edit(
@@ -218,6 +249,7 @@ impl SettingsDialogState {
             &mut layout_y,
             content_width,
             ID_BLUR_EDIT,
+            WINDOW_STYLE(0),
         ));
         if let Some(edit) = self.blur_edit {
             set_edit_text(edit, &self.current_blur_radius());
@@ -264,7 +296,35 @@ impl SettingsDialogState {
             );
         }
 
-        layout_y += 30;
+        layout_y += 40;
+        create_label(
+            hwnd,
+            "Profile used when locking",
+            left,
+            layout_y - 10,
+            content_width,
+        );
+        self.profile_combo = Some(create_combo(
+            hwnd,
+            left,
+            layout_y + 10,
+            content_width,
+            ID_PROFILE_COMBO,
+        ));
+        if let Some(combo) = self.profile_combo {
+            let labels: Vec<String> = std::iter::once(BASE_PROFILE_LABEL.to_string())
+                .chain(self.profile_names.iter().cloned())
+                .collect();
+            populate_combo(combo, &labels);
+            let selected = (*self.settings)
+                .default_profile
+                .as_ref()
+                .and_then(|name| self.profile_names.iter().position(|known| known == name))
+                .map_or(0, |index| index + 1);
+            SendMessageW(combo, CB_SETCURSEL, WPARAM(selected), LPARAM(0));
+        }
+
+        layout_y += 50;
         create_label(hwnd, "Monitor behavior", left, layout_y - 10, content_width);
         self.monitor_mode_combo = Some(create_combo(
             hwnd,
@@ -346,6 +406,7 @@ impl SettingsDialogState {
         }
         layout_y += 30;
         layout_y += 120;
+        self.save_error = Some(create_label(hwnd, "", left, layout_y - 30, content_width));
         create_button(
             hwnd,
             "Apply Changes",
@@ -359,6 +420,32 @@ impl SettingsDialogState {
 
         self.update_blur_edit_state();
         self.update_monitor_control_state();
+        self.disable_locked_controls();
+    }
+
+    fn is_locked(&self, key: &str) -> bool {
+        unsafe { (*self.settings).is_locked(key) }
+    }
+
+    /// Greys out controls whose setting the machine policy enforces.
+    unsafe fn disable_locked_controls(&self) {
+        let controls = [
+            (self.password_edit, "password_hash"),
+            (self.duress_edit, "duress"),
+            (self.blur_checkbox, "blur_enabled"),
+            (self.show_checkbox, "open_settings_on_startup"),
+            (self.dismiss_checkbox, "dismiss_notifications_on_startup"),
+            (self.monitor_mode_combo, "monitor_mode"),
+            (self.profile_combo, "default_profile"),
+            (self.text_checkbox, "text_on_all_monitors"),
+        ];
+        for (control, key) in controls {
+            if let Some(control) = control
+                && self.is_locked(key)
+            {
+                set_control_enabled(control, false);
+            }
+        }
     }
 
     fn bool_flag<F>(&self, getter: F) -> bool
@@ -369,7 +456,7 @@ impl SettingsDialogState {
     }
 
     fn blur_controls_enabled(&self) -> bool {
-        self.blur_enabled
+        self.blur_enabled && !self.is_locked("blur_radius")
     }
 
     unsafe fn blur_checkbox_changed(&mut self) {
@@ -409,7 +496,7 @@ impl SettingsDialogState {
     }
 
     unsafe fn update_monitor_control_state(&self) {
-        let enabled = self.monitor_mode_is_custom();
+        let enabled = self.monitor_mode_is_custom() && !self.is_locked("disable_monitors");
         if let Some(combo) = self.monitor_combo {
             set_control_enabled(combo, enabled);
         }
@@ -425,7 +512,7 @@ impl SettingsDialogState {
     }
 
     unsafe fn add_selected_monitor(&mut self) {
-        if !self.monitor_mode_is_custom() {
+        if !self.monitor_mode_is_custom() || self.is_locked("disable_monitors") {
             return;
         }
         let combo = match self.monitor_combo {
@@ -454,7 +541,7 @@ impl SettingsDialogState {
     }
 
     unsafe fn remove_selected_monitor(&mut self) {
-        if !self.monitor_mode_is_custom() {
+        if !self.monitor_mode_is_custom() || self.is_locked("disable_monitors") {
             return;
         }
         let list = match self.monitor_list {
@@ -471,17 +558,47 @@ impl SettingsDialogState {
         }
     }
 
-    unsafe fn apply_settings(&mut self) {
-        if let Some(password) = self.password_edit {
-            let value = read_text(password);
-            if !value.trim().is_empty() {
-                (*self.settings).password = value;
+    /// The text typed into `edit`, unless the field is empty or `key` is
+    /// pinned by the machine policy.
+    unsafe fn entered_secret(&self, edit: Option<HWND>, key: &str) -> Option<(HWND, SecretString)> {
+        let edit = edit.filter(|_| !self.is_locked(key))?;
+        let value = read_secret_text(edit);
+        (!value.expose().trim().is_empty()).then_some((edit, value))
+    }
+
+    /// Returns `false`, leaving the rejected input in place and the reason in
+    /// the error label, when a new password breaks the password policy.
+    unsafe fn apply_settings(&mut self) -> bool {
+        // The duress password is checked before the password is changed, so a
+        // rejected one cannot leave a half-applied change behind.
+        let password = self.entered_secret(self.password_edit, "password_hash");
+        let duress = self.entered_secret(self.duress_edit, "duress");
+        if let Some((_, value)) = &duress
+            && let Err(violation) = (*self.settings)
+                .password_policy
+                .check_password(value.expose())
+        {
+            self.show_password_error(&format!("Duress password: {violation}"));
+            return false;
+        }
+        if let Some((edit, value)) = password {
+            if let Err(violation) = (*self.settings).change_password(value.expose()) {
+                self.show_password_error(&violation.to_string());
+                return false;
             }
+            set_edit_text(edit, "");
+        }
+        if let Some((edit, value)) = duress {
+            (*self.settings).set_duress_password(value.expose());
+            set_edit_text(edit, "");
         }
-        if let Some(blur_edit) = self.blur_edit {
+        self.show_password_error("");
+        if let Some(blur_edit) = self.blur_edit
+            && !self.is_locked("blur_radius")
+        {
             let value = read_text(blur_edit);
             if let Ok(parsed) = value.trim().parse::<usize>() {
-                (*self.settings).blur_radius = parsed.max(1).min(64);
+                (*self.settings).blur_radius = parsed.clamp(MIN_BLUR_RADIUS, MAX_BLUR_RADIUS);
             }
         }
         if let Some(show) = self.show_checkbox {
@@ -493,15 +610,37 @@ impl SettingsDialogState {
         if let Some(text) = self.text_checkbox {
             self.text_on_all_monitors = checkbox_checked(text);
         }
+        if let Some(combo) = self.profile_combo {
+            let index = SendMessageW(combo, CB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
+            if index >= 0 {
+                (*self.settings).default_profile = (index as usize)
+                    .checked_sub(1)
+                    .and_then(|position| self.profile_names.get(position).cloned());
+            }
+        }
         (*self.settings).monitor_mode = self.monitor_mode;
         (*self.settings).blur_enabled = self.blur_enabled;
         (*self.settings).disable_monitors = self.selected_monitors.clone();
         (*self.settings).text_on_all_monitors = self.text_on_all_monitors;
+        if let Err(err) = persist_settings(&*self.settings) {
+            self.show_save_error(&format!("Not saved: {err}"));
+            return false;
+        }
+        self.show_save_error("");
         self.applied = true;
+        true
+    }
+
+    unsafe fn show_password_error(&self, message: &str) {
+        if let Some(label) = self.password_error {
+            set_edit_text(label, message);
+        }
     }
 
-    fn current_password(&self) -> String {
-        unsafe { (*self.settings).password.clone() }
+    unsafe fn show_save_error(&self, message: &str) {
+        if let Some(label) = self.save_error {
+            set_edit_text(label, message);
+        }
     }
 
     fn current_blur_radius(&self) -> String {
@@ -519,10 +658,14 @@ unsafe fn handle_command(hwnd: HWND, state: &mut SettingsDialogState, wparam: WP
             ID_TEXT_ON_MONITORS => state.text_checkbox_changed(),
             ID_MONITOR_ADD => state.add_selected_monitor(),
             ID_MONITOR_REMOVE => state.remove_selected_monitor(),
-            ID_APPLY_BUTTON => state.apply_settings(),
-            ID_CLOSE_BUTTON => {
+            ID_APPLY_BUTTON => {
                 state.apply_settings();
-                let _ = DestroyWindow(hwnd);
+            }
+            ID_CLOSE_BUTTON => {
+                let applied = state.apply_settings();
+                if applied {
+                    let _ = DestroyWindow(hwnd);
+                }
             }
             _ => {}
         },
@@ -540,9 +683,9 @@ unsafe fn dialog_state_mut(hwnd: HWND) -> Option<&'static mut SettingsDialogStat
     ptr.as_mut()
 }
 
-unsafe fn create_label(hwnd: HWND, text: &str, x: i32, y: i32, width: i32) {
+unsafe fn create_label(hwnd: HWND, text: &str, x: i32, y: i32, width: i32) -> HWND {
     let wide = to_wide(text);
-    let _ = CreateWindowExW(
+    CreateWindowExW(
         WINDOW_EX_STYLE(0),
         w!("STATIC"),
         PCWSTR(wide.as_ptr()),
@@ -555,7 +698,7 @@ unsafe fn create_label(hwnd: HWND, text: &str, x: i32, y: i32, width: i32) {
         HMENU(0),
         None,
         None,
-    );
+    )
 }
 
 unsafe fn create_labeled_edit(
@@ -565,13 +708,14 @@ unsafe fn create_labeled_edit(
     layout_y: &mut i32,
     width: i32,
     id: isize,
+    style: WINDOW_STYLE,
 ) -> HWND {
     create_label(hwnd, label, left, *layout_y - 2, width);
     let edit = CreateWindowExW(
         WINDOW_EX_STYLE(WS_EX_CLIENTEDGE.0),
         w!("EDIT"),
         PCWSTR::null(),
-        WS_CHILD | WS_VISIBLE | WS_TABSTOP,
+        WS_CHILD | WS_VISIBLE | WS_TABSTOP | style,
         left,
         *layout_y + 18,
         width,
@@ -759,6 +903,17 @@ unsafe fn read_text(control: HWND) -> String {
     }
 }
 
+unsafe fn read_secret_text(control: HWND) -> SecretString {
+    let mut buffer = Zeroizing::new(vec![0u16; 256]);
+    let len = GetWindowTextW(control, &mut buffer);
+    let mut text = String::with_capacity(buffer.len() * 3);
+    text.extend(
+        char::decode_utf16(buffer[..len.max(0) as usize].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+    );
+    SecretString::new(text)
+}
+
 fn to_wide(text: &str) -> Vec<u16> {
     let mut wide: Vec<u16> = OsStr::new(text).encode_wide().collect();
     wide.push(0);
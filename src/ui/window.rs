@@ -2,15 +2,44 @@
 
 use super::settings_dialog::show_settings_dialog;
 use crate::{
+    audit::{self, AuditEvent, AuditLog},
+    auth::{AuthChain, AuthOutcome, totp::unix_now},
+    backoff::AttemptTracker,
     blur::blur_buffer,
     capture::{build_bitmap_info, capture_screen},
-    config::{CLASS_NAME, TIMER_ID, TIMER_INTERVAL_MS},
+    challenge::verify_response,
+    cli::GlobalOptions,
+    config::{CLASS_NAME, TIMER_ID, TIMER_INTERVAL_MS, WM_KEYFILE_FOUND},
+    duress::{DuressAction, run_duress_actions},
+    guest::{GuestSession, consume_guest_password, record_guest_use_of},
+    input::PasswordInput,
+    keyfile::{find_matching_keyfile, is_matching_keyfile},
+    layers::read_layered_settings,
     monitors::{destroy_overlays, spawn_overlays},
+    recovery::{consume_recovery_code, looks_like_recovery_code, remaining_recovery_codes},
+    reload::{apply_reload, file_stamp, plan_reload},
     render::{draw_overlay, settings_button_rect},
-    settings::Settings,
-    state::{AppState, app_state, arm_warning, mark_warning},
+    settings::{
+        Settings, SettingsError, changed_fields, load_settings, persist_settings, settings_path,
+    },
+    state::{
+        AppState, active_challenge, app_state, input_blocked, mark_warning, register_failed_attempt,
+    },
+    token::{
+        UsedNonce, looks_like_unlock_token, machine_name, take_token_files, verify_unlock_token,
+    },
+};
+use chrono::Local;
+use std::{
+    mem,
+    path::PathBuf,
+    sync::{
+        Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
 };
-use std::mem;
 use windows::{
     Win32::{
         Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
@@ -23,10 +52,10 @@ use windows::{
         UI::WindowsAndMessaging::{
             CS_HREDRAW, CS_VREDRAW, ClipCursor, CreateWindowExW, DefWindowProcW, DestroyWindow,
             DispatchMessageW, GetMessageW, HMENU, HWND_TOPMOST, KillTimer, LoadCursorW, MSG,
-            MessageBoxW, PostQuitMessage, RegisterClassW, SC_CLOSE, SW_SHOW, SWP_NOMOVE,
-            SWP_NOSIZE, SWP_SHOWWINDOW, SetCursorPos, SetForegroundWindow, SetTimer, SetWindowPos,
-            ShowCursor, ShowWindow, TranslateMessage, WINDOW_EX_STYLE, WM_ACTIVATE, WM_CHAR,
-            WM_CLOSE, WM_CREATE, WM_DESTROY, WM_ERASEBKGND, WM_KEYDOWN, WM_LBUTTONDOWN,
+            MessageBoxW, PostMessageW, PostQuitMessage, RegisterClassW, SC_CLOSE, SW_SHOW,
+            SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW, SetCursorPos, SetForegroundWindow, SetTimer,
+            SetWindowPos, ShowCursor, ShowWindow, TranslateMessage, WINDOW_EX_STYLE, WM_ACTIVATE,
+            WM_CHAR, WM_CLOSE, WM_CREATE, WM_DESTROY, WM_ERASEBKGND, WM_KEYDOWN, WM_LBUTTONDOWN,
             WM_MOUSEMOVE, WM_PAINT, WM_SYSCOMMAND, WM_SYSKEYDOWN, WM_TIMER, WNDCLASS_STYLES,
             WNDCLASSW, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP, WS_VISIBLE,
         },
@@ -34,7 +63,7 @@ use windows::{
     core::{Result, w},
 };
 
-pub fn build_app_state(settings: &Settings) -> Result<AppState> {
+pub fn build_app_state(settings: Settings) -> Result<AppState> {
     let mut captured = unsafe { capture_screen()? };
     if settings.blur_enabled {
         blur_buffer(
@@ -50,20 +79,30 @@ pub fn build_app_state(settings: &Settings) -> Result<AppState> {
         height: captured.height,
         pixels: captured.pixels,
         bitmap_info,
-        password: settings.password.clone(),
-        input: String::new(),
+        verifier: settings.password_verifier(),
+        auth: AuthChain::from_settings(&settings),
+        attempts: AttemptTracker::new(settings.backoff.clone()),
+        locked_since: Instant::now(),
+        input: PasswordInput::new(),
         warning_since: None,
-        settings: settings.clone(),
+        challenge: None,
+        settings,
+        settings_stamp: file_stamp(&settings_path()),
+        global: GlobalOptions::default(),
         monitor_windows: Vec::new(),
     })
 }
 
 pub unsafe fn refresh_display(settings: Settings) -> Result<()> {
-    let mut new_state = build_app_state(&settings)?;
+    let mut new_state = build_app_state(settings)?;
     let instance = GetModuleHandleW(None)?;
     new_state.monitor_windows = spawn_overlays(instance.into(), &new_state.settings);
     let mut guard = app_state().lock().unwrap();
     let old_windows = mem::take(&mut guard.monitor_windows);
+    new_state.locked_since = guard.locked_since;
+    new_state.attempts = guard.attempts.clone();
+    new_state.challenge = guard.challenge.take();
+    new_state.global = mem::take(&mut guard.global);
     drop(guard);
     destroy_overlays(&old_windows);
     let mut guard = app_state().lock().unwrap();
@@ -138,6 +177,9 @@ pub unsafe fn create_window_loop() -> Result<()> {
         DispatchMessageW(&message);
     }
 
+    if let Some(handle) = DURESS_ACTIONS.lock().unwrap().take() {
+        let _ = handle.join();
+    }
     Ok(())
 }
 
@@ -166,11 +208,16 @@ unsafe extern "system" fn window_proc(
         }
         WM_LBUTTONDOWN => {
             let point = point_from_lparam(lparam);
-            let rect = {
+            let button = {
                 let state = app_state().lock().unwrap();
-                settings_button_rect(&state)
+                state
+                    .settings
+                    .show_settings_button
+                    .then(|| settings_button_rect(&state))
             };
-            if PtInRect(&rect, point).0 != 0 {
+            if let Some(rect) = button
+                && PtInRect(&rect, point).0 != 0
+            {
                 handle_settings_click(hwnd);
             }
             LRESULT(0)
@@ -188,9 +235,16 @@ unsafe extern "system" fn window_proc(
             }
         }
         WM_TIMER => {
+            poll_token_directory(hwnd);
+            poll_keyfile(hwnd);
+            poll_settings_file();
             let _ = InvalidateRect(hwnd, None, false);
             LRESULT(0)
         }
+        WM_KEYFILE_FOUND => {
+            keyfile_found(hwnd);
+            LRESULT(0)
+        }
         WM_KEYDOWN | WM_SYSKEYDOWN => LRESULT(0),
         WM_CLOSE => LRESULT(0),
         WM_ACTIVATE => {
@@ -200,6 +254,9 @@ unsafe extern "system" fn window_proc(
         WM_DESTROY => {
             {
                 let mut state = app_state().lock().unwrap();
+                audit::record(AuditEvent::SessionEnded {
+                    duration_secs: state.locked_since.elapsed().as_secs(),
+                });
                 destroy_overlays(&state.monitor_windows);
                 state.monitor_windows.clear();
             }
@@ -286,47 +343,330 @@ unsafe fn draw_buffered(hdc: windows::Win32::Graphics::Gdi::HDC, state: &AppStat
 }
 
 fn handle_char(hwnd: HWND, char_code: u32) {
-    let mut state = app_state().lock().unwrap();
+    let mut guard = app_state().lock().unwrap();
+    let state = &mut *guard;
+    if input_blocked(state) {
+        return;
+    }
     match char_code {
-        0x08 => {
-            state.input.pop();
-        }
+        0x08 => state.input.backspace(),
         0x0D => {
-            if state.input == state.password {
-                drop(state);
-                unsafe {
-                    release_locks();
-                    let _ = DestroyWindow(hwnd);
-                }
+            let typed = state.input.normalized();
+            if state.auth.stage_index() == 0
+                && (try_challenge_response(state, typed.expose())
+                    || try_recovery_code(state, typed.expose())
+                    || try_guest_password(state, typed.expose())
+                    || try_unlock_token(state, typed.expose()))
+            {
+                finish_unlock(hwnd, guard);
                 return;
-            } else {
-                state.input.clear();
-                arm_warning(&mut state);
             }
-        }
-        0x1B => {}
-        ch => {
-            if let Some(c) = char::from_u32(ch) {
-                if c.is_ascii() && !c.is_ascii_control() {
-                    state.input.push(c);
+            let outcome = state.auth.submit(typed.expose());
+            record_factor_use(state);
+            match outcome {
+                AuthOutcome::Accept => {
+                    if state.auth.unlocked_under_duress()
+                        && let Some(duress) = &state.settings.duress
+                    {
+                        spawn_duress_actions(duress.actions.clone());
+                    }
+                    end_guest_session(state);
+                    finish_unlock(hwnd, guard);
+                    return;
                 }
+                AuthOutcome::NeedMoreInput => state.input.clear(),
+                AuthOutcome::Reject => register_failed_attempt(state),
             }
         }
+        0x1B => {}
+        unit => state.input.push_utf16(unit as u16),
     }
 
-    drop(state);
+    drop(guard);
     unsafe {
         let _ = InvalidateRect(hwnd, None, false);
     }
 }
 
+/// Every unlock path ends here, so the failed-attempt backoff is reset
+/// whichever factor let the user in.
+fn finish_unlock(hwnd: HWND, mut guard: MutexGuard<'_, AppState>) {
+    guard.input.clear();
+    guard.attempts.record_success();
+    drop(guard);
+    audit::record(AuditEvent::Unlocked);
+    unsafe {
+        release_locks();
+        let _ = DestroyWindow(hwnd);
+    }
+}
+
+/// The duress actions started by the last duress unlock; joined before the
+/// process exits so a slow marker file or command is not cut short.
+static DURESS_ACTIONS: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// Runs the duress actions on a worker thread so a slow disk or command
+/// cannot hold up the unlock, which has to look like any other.
+fn spawn_duress_actions(actions: Vec<DuressAction>) {
+    let handle = thread::spawn(move || run_duress_actions(&actions, &AuditLog::default()));
+    *DURESS_ACTIONS.lock().unwrap() = Some(handle);
+}
+
+/// A helpdesk response to the challenge on screen stands in for the factor
+/// chain until the challenge expires.
+fn try_challenge_response(state: &mut AppState, response: &str) -> bool {
+    let accepted = match (&state.settings.challenge, active_challenge(state)) {
+        (Some(config), Some(challenge)) => verify_response(config, challenge, response),
+        _ => false,
+    };
+    if accepted {
+        state.challenge = None;
+        audit::record(AuditEvent::ChallengeUnlock);
+    }
+    accepted
+}
+
+/// Applies `update` to the file on disk rather than the in-memory copy, so
+/// settings changed elsewhere since the lock started are kept. A file that no
+/// longer loads is left alone. Failures are audited as `SettingsSaveFailed`.
+fn update_stored_settings(
+    update: impl FnOnce(&mut Settings),
+) -> std::result::Result<(), SettingsError> {
+    let result = load_settings().and_then(|mut stored| {
+        update(&mut stored);
+        persist_settings(&stored)
+    });
+    if let Err(err) = &result {
+        audit::record(AuditEvent::SettingsSaveFailed {
+            reason: err.to_string(),
+        });
+    }
+    result
+}
+
+/// Saves what the factors must remember beyond this lock screen, such as the
+/// last TOTP step, both in memory and in the file on disk.
+fn record_factor_use(state: &mut AppState) {
+    if state.auth.record_use(&mut state.settings.authenticators) {
+        let auth = &state.auth;
+        let _ = update_stored_settings(|stored| {
+            auth.record_use(&mut stored.authenticators);
+        });
+    }
+}
+
+/// Emergency codes bypass the configured factors. A used code is marked in
+/// the file on disk as well, so it stays spent after this session.
+fn try_recovery_code(state: &mut AppState, code: &str) -> bool {
+    if !looks_like_recovery_code(code)
+        || !consume_recovery_code(&mut state.settings.recovery_codes, code)
+    {
+        return false;
+    }
+    let _ = update_stored_settings(|stored| {
+        consume_recovery_code(&mut stored.recovery_codes, code);
+    });
+    audit::record(AuditEvent::RecoveryCodeUsed {
+        remaining: remaining_recovery_codes(&state.settings.recovery_codes),
+    });
+    true
+}
+
+/// Guest passwords stand in for the whole factor chain, but only inside their
+/// validity window. The spent use and the expiry reminder shown on the next
+/// lock screen are written to the file on disk.
+fn try_guest_password(state: &mut AppState, password: &str) -> bool {
+    if state.settings.guest_passwords.is_empty() {
+        return false;
+    }
+    let Some(index) =
+        consume_guest_password(&mut state.settings.guest_passwords, password, Local::now())
+    else {
+        return false;
+    };
+    let guest = &state.settings.guest_passwords[index];
+    let _ = update_stored_settings(|stored| {
+        record_guest_use_of(&mut stored.guest_passwords, &guest.hash);
+        stored.guest_session = Some(GuestSession::for_guest(guest));
+    });
+    audit::record(AuditEvent::GuestUnlock {
+        label: guest.label.clone(),
+    });
+    true
+}
+
+/// The owner is back, so the next lock screen should not mention guest access.
+fn end_guest_session(state: &mut AppState) {
+    if state.settings.guest_session.take().is_some() {
+        let _ = update_stored_settings(|stored| stored.guest_session = None);
+    }
+}
+
+/// Administrator-signed tokens also stand in for the factor chain. The nonce
+/// is written to the file on disk so the token cannot be replayed later; if
+/// that fails the token is refused, though it stays spent for this session.
+fn try_unlock_token(state: &mut AppState, text: &str) -> bool {
+    let Some(config) = &state.settings.unlock_token else {
+        return false;
+    };
+    if !looks_like_unlock_token(text) {
+        return false;
+    }
+    let token = match verify_unlock_token(
+        text,
+        config,
+        &machine_name(),
+        unix_now(),
+        &mut state.settings.used_token_nonces,
+    ) {
+        Ok(token) => token,
+        Err(err) => {
+            eprintln!("unlock token rejected: {err}");
+            return false;
+        }
+    };
+    let saved = update_stored_settings(|stored| {
+        stored.used_token_nonces.push(UsedNonce {
+            nonce: token.nonce.clone(),
+            expires: token.expires,
+        });
+    });
+    if saved.is_err() {
+        return false;
+    }
+    audit::record(AuditEvent::TokenUnlock { nonce: token.nonce });
+    true
+}
+
+/// Tokens dropped into the watched directory are picked up on the redraw
+/// timer; each file is deleted once read.
+fn poll_token_directory(hwnd: HWND) {
+    let mut guard = app_state().lock().unwrap();
+    let Some(directory) = guard
+        .settings
+        .unlock_token
+        .as_ref()
+        .map(|config| config.directory())
+    else {
+        return;
+    };
+    for text in take_token_files(&directory) {
+        if try_unlock_token(&mut guard, &text) {
+            finish_unlock(hwnd, guard);
+            return;
+        }
+    }
+}
+
+/// Set while a keyfile probe runs, so a slow drive cannot pile up threads.
+static KEYFILE_PROBE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The last path the probe thread matched, waiting for `keyfile_found`.
+static KEYFILE_MATCH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Releases the lock as soon as the configured keyfile is reachable, e.g.
+/// when the USB stick carrying it is plugged in. Probing every drive letter
+/// can stall on a slow or empty drive, so it runs on a worker thread that
+/// posts `WM_KEYFILE_FOUND` back to the lock window.
+fn poll_keyfile(hwnd: HWND) {
+    let Some(config) = app_state().lock().unwrap().settings.keyfile.clone() else {
+        return;
+    };
+    if KEYFILE_PROBE_RUNNING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    thread::spawn(move || {
+        if let Some(path) = find_matching_keyfile(&config) {
+            *KEYFILE_MATCH.lock().unwrap() = Some(path);
+            let _ = unsafe { PostMessageW(hwnd, WM_KEYFILE_FOUND, WPARAM(0), LPARAM(0)) };
+        }
+        KEYFILE_PROBE_RUNNING.store(false, Ordering::Release);
+    });
+}
+
+/// Handles `WM_KEYFILE_FOUND`. The message itself proves nothing, so the
+/// path left by the probe thread is hashed again before unlocking.
+fn keyfile_found(hwnd: HWND) {
+    let Some(path) = KEYFILE_MATCH.lock().unwrap().take() else {
+        return;
+    };
+    let guard = app_state().lock().unwrap();
+    let verified = guard
+        .settings
+        .keyfile
+        .as_ref()
+        .is_some_and(|config| is_matching_keyfile(config, &path));
+    if !verified {
+        return;
+    }
+    audit::record(AuditEvent::KeyfileUnlock {
+        path: path.display().to_string(),
+    });
+    finish_unlock(hwnd, guard);
+}
+
+/// Picks up edits to the settings file made while locked. Fields that only
+/// change the look of the lock screen go through `refresh_display`; the rest
+/// wait for the next lock. Every outcome goes to the audit log.
+unsafe fn poll_settings_file() {
+    let stamp = file_stamp(&settings_path());
+    let (current, global) = {
+        let mut guard = app_state().lock().unwrap();
+        if guard.settings_stamp == stamp {
+            return;
+        }
+        guard.settings_stamp = stamp;
+        (guard.settings.clone(), guard.global.clone())
+    };
+    let incoming = match read_layered_settings(&global) {
+        Ok(incoming) => incoming,
+        Err(err) => {
+            eprintln!("settings reload rejected: {err}");
+            audit::record(AuditEvent::SettingsReloadRejected {
+                reason: err.to_string(),
+            });
+            return;
+        }
+    };
+    let plan = plan_reload(&current, &incoming);
+    if plan.is_empty() {
+        return;
+    }
+    if !plan.applied.is_empty()
+        && let Err(err) = refresh_display(apply_reload(&current, &incoming, &plan))
+    {
+        eprintln!("refresh failed: {err:?}");
+    }
+    audit::record(AuditEvent::SettingsReloaded {
+        applied: plan.applied,
+        deferred: plan.deferred,
+    });
+}
+
 unsafe fn handle_settings_click(hwnd: HWND) {
     let mut settings = {
-        let state = app_state().lock().unwrap();
+        let mut state = app_state().lock().unwrap();
+        if input_blocked(&state) {
+            return;
+        }
+        if !state.verifier.verify(state.input.normalized().expose()) {
+            register_failed_attempt(&mut state);
+            drop(state);
+            let _ = InvalidateRect(hwnd, None, false);
+            return;
+        }
+        // Only the password was checked, so the backoff stays as it is;
+        // `finish_unlock` resets it once the whole chain has passed.
+        state.input.clear();
         state.settings.clone()
     };
+    let before = settings.clone();
     if let Ok(applied) = show_settings_dialog(&mut settings) {
         if applied {
+            let fields = changed_fields(&before, &settings);
+            if !fields.is_empty() {
+                audit::record(AuditEvent::SettingsChanged { fields });
+            }
             if let Err(err) = refresh_display(settings) {
                 eprintln!("refresh failed: {err:?}");
             } else {
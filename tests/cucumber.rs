@@ -1,22 +1,173 @@
 use async_trait::async_trait;
+use chrono::{Duration as ChronoDuration, Local};
 use cucumber::{WorldInit, given, then, when};
-use std::convert::Infallible;
+use std::{
+    convert::Infallible,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use dwmlock::settings::{MonitorBlankingMode, Settings};
+use dwmlock::{
+    audit::{AuditEvent, AuditLog, summarize},
+    auth::{
+        AuthChain, AuthOutcome, AuthenticatorConfig, TotpAuthenticator,
+        shares::enroll_shares,
+        totp::{base32_encode, enroll_totp, totp_code, unix_now},
+    },
+    backoff::{AttemptTracker, BackoffPolicy},
+    bundle::{
+        BUNDLE_VERSION, BundleError, ImportPlan, SettingsBundle, export_bundle, parse_bundle,
+        plan_import, read_bundle, write_bundle,
+    },
+    challenge::{Challenge, ChallengeConfig, challenge_due, verify_response},
+    cli::{
+        CONFIG_HELP, Command, EXIT_USAGE, HASH_PASSWORD_HELP, Invocation, LOCK_HELP, MAIN_HELP,
+        RESPOND_HELP, SETTINGS_HELP, TOKEN_HELP, UsageError, parse_args,
+    },
+    config::DEFAULT_PASSWORD,
+    duress::{DuressAction, fire_duress_actions, run_duress_actions},
+    guest::{GuestPassword, GuestSession, consume_guest_password},
+    input::PasswordInput,
+    keyfile::{KeyfileConfig, find_matching_keyfile, is_matching_keyfile, wildcard_match},
+    layers::{
+        ConfigLayer, HIDDEN_VALUE, environment_overrides, get_setting, list_settings,
+        reset_setting, resolve_settings, set_setting,
+    },
+    migrations::{CURRENT_SCHEMA_VERSION, MigrationError, migrate_settings_value},
+    password::PasswordHash,
+    policy::PolicyViolation,
+    profiles::resolve_profile,
+    recovery::{consume_recovery_code, regenerate_recovery_codes, remaining_recovery_codes},
+    reload::{ReloadPlan, apply_reload, plan_reload},
+    schema::{SCHEMA_PATH, settings_schema, settings_schema_json},
+    secret::SecretString,
+    settings::{
+        MonitorBlankingMode, Settings, SettingsError, changed_fields, check_settings_file,
+        load_settings_from, parse_settings, persist_settings_to, read_settings_from,
+    },
+    shamir::{Share, combine_shares, split_secret},
+    token::{
+        TokenError, UnlockToken, UnlockTokenConfig, generate_signing_key, prune_used_nonces,
+        sign_unlock_token, signing_key_from_hex, take_token_files, verify_unlock_token,
+    },
+};
 
 #[derive(Debug, WorldInit)]
 struct LockWorld {
     settings: Settings,
+    chain: Option<AuthChain>,
+    outcome: Option<AuthOutcome>,
+    totp_secret: Vec<u8>,
+    totp_digits: u32,
+    totp: Option<TotpAuthenticator>,
+    enrollment_uri: String,
+    attempts: Option<AttemptTracker>,
+    now: Instant,
+    audit: Option<AuditLog>,
+    snapshot: Option<Settings>,
+    input: PasswordInput,
+    recovery_codes: Vec<SecretString>,
+    previous_recovery_codes: Vec<SecretString>,
+    recovery_accepted: Option<bool>,
+    marker_path: Option<PathBuf>,
+    guest_accepted: Option<bool>,
+    token_private_key: Option<SecretString>,
+    token: String,
+    token_result: Option<Result<UnlockToken, TokenError>>,
+    shares: Vec<Share>,
+    combined: Vec<u8>,
+    unlock_shares: Vec<SecretString>,
+    previous_unlock_shares: Vec<SecretString>,
+    keyfile_dir: PathBuf,
+    keyfile_match: Option<PathBuf>,
+    challenge: Option<Challenge>,
+    password_change: Option<Result<(), PolicyViolation>>,
+    migrated_value: Option<serde_json::Value>,
+    upgraded: Option<bool>,
+    parsed: Option<Result<Settings, SettingsError>>,
+    settings_file: PathBuf,
+    settings_original: Vec<u8>,
+    settings_check: Option<Result<(), SettingsError>>,
+    settings_save: Option<Result<(), SettingsError>>,
+    policy_file: Option<serde_json::Value>,
+    environment: Vec<(String, String)>,
+    command_line: Vec<String>,
+    resolved: Option<Result<Settings, SettingsError>>,
+    on_disk: Option<Settings>,
+    reload_plan: Option<ReloadPlan>,
+    reloaded: Option<Settings>,
+    invocation: Option<Result<Invocation, UsageError>>,
+    setting_read: Option<Option<serde_json::Value>>,
+    listing: Vec<(String, serde_json::Value, ConfigLayer)>,
+    bundle: Option<SettingsBundle>,
+    import: Option<Result<ImportPlan, BundleError>>,
 }
 
 impl Default for LockWorld {
     fn default() -> Self {
         Self {
             settings: Settings::default(),
+            chain: None,
+            outcome: None,
+            totp_secret: Vec::new(),
+            totp_digits: 6,
+            totp: None,
+            enrollment_uri: String::new(),
+            attempts: None,
+            now: Instant::now(),
+            audit: None,
+            snapshot: None,
+            input: PasswordInput::new(),
+            recovery_codes: Vec::new(),
+            previous_recovery_codes: Vec::new(),
+            recovery_accepted: None,
+            marker_path: None,
+            guest_accepted: None,
+            token_private_key: None,
+            token: String::new(),
+            token_result: None,
+            shares: Vec::new(),
+            combined: Vec::new(),
+            unlock_shares: Vec::new(),
+            previous_unlock_shares: Vec::new(),
+            keyfile_dir: PathBuf::new(),
+            keyfile_match: None,
+            challenge: None,
+            password_change: None,
+            migrated_value: None,
+            upgraded: None,
+            parsed: None,
+            settings_file: PathBuf::new(),
+            settings_original: Vec::new(),
+            settings_check: None,
+            settings_save: None,
+            policy_file: None,
+            environment: Vec::new(),
+            command_line: Vec::new(),
+            resolved: None,
+            on_disk: None,
+            reload_plan: None,
+            reloaded: None,
+            invocation: None,
+            setting_read: None,
+            listing: Vec::new(),
+            bundle: None,
+            import: None,
         }
     }
 }
 
+fn temp_dir(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or(0);
+    let dir = std::env::temp_dir().join(format!("dwmlock-{label}-{}-{nanos}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
+
 #[async_trait(?Send)]
 impl cucumber::World for LockWorld {
     type Error = Infallible;
@@ -70,6 +221,1860 @@ fn assert_default_disable_list(world: &mut LockWorld) {
     );
 }
 
+#[given(regex = r#"^a legacy settings file with password "(.*)"$"#)]
+fn legacy_settings_file(world: &mut LockWorld, password: String) {
+    let json = format!(r#"{{"password": "{password}"}}"#);
+    world.settings = serde_json::from_str(&json).expect("legacy settings parse");
+}
+
+#[when(regex = r#"^the user sets the password "(.*)"$"#)]
+fn set_password(world: &mut LockWorld, password: String) {
+    world.settings.set_password(&password);
+}
+
+#[when("the legacy password is migrated")]
+fn migrate_legacy_password(world: &mut LockWorld) {
+    assert!(world.settings.migrate_legacy_password());
+}
+
+#[then(regex = r#"^the saved settings do not contain "(.*)"$"#)]
+fn assert_not_serialized(world: &mut LockWorld, secret: String) {
+    let json = serde_json::to_string(&world.settings).expect("settings serialize");
+    assert!(!json.contains(&secret), "secret leaked into {json}");
+}
+
+#[then(regex = r#"^the password "(.*)" unlocks$"#)]
+fn assert_password_unlocks(world: &mut LockWorld, password: String) {
+    assert!(world.settings.password_verifier().verify(&password));
+}
+
+#[then(regex = r#"^the password "(.*)" does not unlock$"#)]
+fn assert_password_rejected(world: &mut LockWorld, password: String) {
+    assert!(!world.settings.password_verifier().verify(&password));
+}
+
+#[when(regex = r#"^the user adds the PIN "(.*)"$"#)]
+fn add_pin(world: &mut LockWorld, pin: String) {
+    world
+        .settings
+        .authenticators
+        .push(AuthenticatorConfig::Pin {
+            hash: PasswordHash::new(&pin),
+        });
+}
+
+#[when("the unlock chain is built")]
+fn build_chain(world: &mut LockWorld) {
+    world.chain = Some(AuthChain::from_settings(&world.settings));
+}
+
+#[when(regex = r#"^"(.*)" is submitted$"#)]
+fn submit_input(world: &mut LockWorld, input: String) {
+    let chain = world.chain.as_mut().expect("chain built");
+    world.outcome = Some(chain.submit(&input));
+}
+
+#[then(regex = r#"^the unlock prompt is "(.*)"$"#)]
+fn assert_prompt(world: &mut LockWorld, prompt: String) {
+    assert_eq!(world.chain.as_ref().expect("chain built").prompt(), prompt);
+}
+
+#[then("the unlock is accepted")]
+fn assert_accepted(world: &mut LockWorld) {
+    assert_eq!(world.outcome, Some(AuthOutcome::Accept));
+}
+
+#[then("the unlock is rejected")]
+fn assert_rejected(world: &mut LockWorld) {
+    assert_eq!(world.outcome, Some(AuthOutcome::Reject));
+}
+
+#[then("more input is needed")]
+fn assert_need_more(world: &mut LockWorld) {
+    assert_eq!(world.outcome, Some(AuthOutcome::NeedMoreInput));
+}
+
+#[given(regex = r#"^the TOTP secret "(.*)" with (\d+) digits and a skew of (\d+) steps$"#)]
+fn totp_secret(world: &mut LockWorld, secret: String, digits: u32, skew: u64) {
+    world.totp_secret = secret.into_bytes();
+    world.totp_digits = digits;
+    world.totp = Some(TotpAuthenticator::new(
+        world.totp_secret.clone(),
+        digits,
+        30,
+        skew,
+    ));
+}
+
+#[then(regex = r#"^the code at (\d+) seconds is "(.*)"$"#)]
+fn assert_totp_code(world: &mut LockWorld, unix_secs: u64, expected: String) {
+    let code = totp_code(&world.totp_secret, unix_secs / 30, world.totp_digits);
+    assert_eq!(code, expected);
+}
+
+#[when(regex = r#"^the code from (\d+) seconds is entered at (\d+) seconds$"#)]
+fn enter_totp_code(world: &mut LockWorld, issued_at: u64, entered_at: u64) {
+    let code = totp_code(&world.totp_secret, issued_at / 30, world.totp_digits);
+    let totp = world.totp.as_mut().expect("totp configured");
+    world.outcome = Some(totp.verify_at(&code, entered_at));
+}
+
+#[given(regex = r#"^the only factor is the TOTP secret "(.*)"$"#)]
+fn only_totp_factor(world: &mut LockWorld, secret: String) {
+    world.totp_secret = secret.into_bytes();
+    world.settings.authenticators = vec![AuthenticatorConfig::Totp {
+        secret: SecretString::new(base32_encode(&world.totp_secret)),
+        digits: 6,
+        period_secs: 30,
+        skew_steps: 1,
+        last_used_step: None,
+    }];
+}
+
+#[when("the current TOTP code is submitted")]
+fn submit_current_totp_code(world: &mut LockWorld) {
+    let code = totp_code(&world.totp_secret, unix_now() / 30, 6);
+    let chain = world.chain.as_mut().expect("chain built");
+    world.outcome = Some(chain.submit(&code));
+}
+
+#[when("the unlock chain records its use in the settings")]
+fn record_chain_use(world: &mut LockWorld) {
+    let chain = world.chain.as_ref().expect("chain built");
+    assert!(chain.record_use(&mut world.settings.authenticators));
+}
+
+#[when(regex = r#"^the user enrolls TOTP for "(.*)"$"#)]
+fn enroll_user_totp(world: &mut LockWorld, account: String) {
+    world.enrollment_uri = enroll_totp(&mut world.settings, &account);
+}
+
+#[then(regex = r#"^the enrollment URI starts with "(.*)"$"#)]
+fn assert_enrollment_uri(world: &mut LockWorld, prefix: String) {
+    assert!(
+        world.enrollment_uri.starts_with(&prefix),
+        "{}",
+        world.enrollment_uri
+    );
+}
+
+#[then(regex = r#"^the unlock chain has (\d+) stages$"#)]
+fn assert_stage_count(world: &mut LockWorld, count: usize) {
+    assert_eq!(
+        AuthChain::from_settings(&world.settings).stage_count(),
+        count
+    );
+}
+
+#[given(
+    regex = r"^a backoff policy with (\d+) free attempts, a (\d+) second base delay capped at (\d+) seconds and a (\d+) second lockout after (\d+) failures$"
+)]
+fn backoff_policy(
+    world: &mut LockWorld,
+    free_attempts: u32,
+    base_delay_secs: u64,
+    max_delay_secs: u64,
+    lockout_secs: u64,
+    lockout_after: u32,
+) {
+    world.attempts = Some(AttemptTracker::new(BackoffPolicy {
+        free_attempts,
+        base_delay_secs,
+        max_delay_secs,
+        lockout_after,
+        lockout_secs,
+    }));
+}
+
+#[when(regex = r"^(\d+) attempts fail$")]
+fn attempts_fail(world: &mut LockWorld, count: u32) {
+    let now = world.now;
+    let attempts = world.attempts.as_mut().expect("policy configured");
+    for _ in 0..count {
+        attempts.record_failure(now);
+    }
+}
+
+#[when(regex = r"^settings with the backoff (.*) are parsed$")]
+fn parse_backoff_settings(world: &mut LockWorld, backoff: String) {
+    let text = format!(r#"{{"schema_version": 1, "backoff": {backoff}}}"#);
+    world.parsed = Some(parse_settings(text.as_bytes()).map(|(settings, _)| settings));
+}
+
+#[then(regex = r"^the loaded backoff delays are at most (\d+) seconds$")]
+fn assert_loaded_backoff(world: &mut LockWorld, secs: u64) {
+    let settings = world
+        .parsed
+        .as_ref()
+        .expect("parsed")
+        .as_ref()
+        .expect("valid");
+    let backoff = &settings.backoff;
+    assert!(backoff.base_delay_secs <= secs);
+    assert!(backoff.max_delay_secs <= secs);
+    assert!(backoff.lockout_secs <= secs);
+}
+
+#[when("an attempt succeeds")]
+fn attempt_succeeds(world: &mut LockWorld) {
+    world
+        .attempts
+        .as_mut()
+        .expect("policy configured")
+        .record_success();
+}
+
+#[when(regex = r"^(\d+) seconds pass$")]
+fn seconds_pass(world: &mut LockWorld, secs: u64) {
+    world.now += Duration::from_secs(secs);
+}
+
+#[then(regex = r"^attempts are blocked for (\d+) seconds$")]
+fn assert_blocked_for(world: &mut LockWorld, secs: u64) {
+    let attempts = world.attempts.as_ref().expect("policy configured");
+    assert_eq!(
+        attempts.remaining(world.now),
+        Some(Duration::from_secs(secs))
+    );
+}
+
+#[then("attempts are allowed")]
+fn assert_attempts_allowed(world: &mut LockWorld) {
+    let attempts = world.attempts.as_ref().expect("policy configured");
+    assert!(!attempts.is_blocked(world.now));
+}
+
+#[then(regex = r"^(\d+) failures are recorded$")]
+fn assert_failure_count(world: &mut LockWorld, count: u32) {
+    let attempts = world.attempts.as_ref().expect("policy configured");
+    assert_eq!(attempts.failures(), count);
+}
+
+#[given(
+    regex = r"^an audit log in a temporary directory rotating at (\d+) bytes and keeping (\d+) files$"
+)]
+fn audit_log(world: &mut LockWorld, max_bytes: u64, retained_files: usize) {
+    let path = temp_dir("audit").join("audit.jsonl");
+    world.audit = Some(AuditLog::new(path, max_bytes, retained_files));
+}
+
+#[when(
+    regex = r"^(\d+) lock sessions? with (\d+) failed attempts lasting (\d+) seconds (?:is|are) audited$"
+)]
+fn audit_sessions(world: &mut LockWorld, sessions: u32, failures: u32, duration_secs: u64) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    for _ in 0..sessions {
+        log.append(AuditEvent::LockStarted).unwrap();
+        for _ in 0..failures {
+            log.append(AuditEvent::FailedAttempt { input_length: 5 })
+                .unwrap();
+        }
+        log.append(AuditEvent::Unlocked).unwrap();
+        log.append(AuditEvent::SessionEnded { duration_secs })
+            .unwrap();
+    }
+}
+
+#[when("a settings save failure is audited")]
+fn audit_save_failure(world: &mut LockWorld) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    log.append(AuditEvent::SettingsSaveFailed {
+        reason: "the settings file cannot be written".to_string(),
+    })
+    .unwrap();
+}
+
+#[then(regex = r"^the audit summary counts (\d+) failed settings saves?$")]
+fn assert_failed_saves(world: &mut LockWorld, count: usize) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    assert_eq!(summarize(&log.read_all().unwrap()).failed_saves, count);
+}
+
+#[then(
+    regex = r"^the audit summary shows (\d+) lock, (\d+) unlock, (\d+) failed attempts and (\d+) locked seconds$"
+)]
+fn assert_audit_summary(
+    world: &mut LockWorld,
+    locks: usize,
+    unlocks: usize,
+    failed_attempts: usize,
+    locked_secs: u64,
+) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let summary = summarize(&log.read_all().unwrap());
+    assert_eq!(summary.locks, locks);
+    assert_eq!(summary.unlocks, unlocks);
+    assert_eq!(summary.failed_attempts, failed_attempts);
+    assert_eq!(summary.total_locked_secs, locked_secs);
+}
+
+#[then("every failed attempt records only the input length")]
+fn assert_failed_attempts_redacted(world: &mut LockWorld) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let text = fs::read_to_string(log.path()).unwrap();
+    for line in text.lines().filter(|line| line.contains("failed_attempt")) {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["event", "input_length", "timestamp"]);
+    }
+}
+
+#[then(regex = r"^at most (\d+) audit files exist$")]
+fn assert_audit_file_count(world: &mut LockWorld, max_files: usize) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let dir = log.path().parent().unwrap();
+    assert!(fs::read_dir(dir).unwrap().count() <= max_files);
+}
+
+#[then(regex = r"^fewer than (\d+) audit records remain$")]
+fn assert_audit_records_pruned(world: &mut LockWorld, limit: usize) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let records = log.read_all().unwrap();
+    assert!(!records.is_empty() && records.len() < limit);
+}
+
+#[when("a settings snapshot is taken")]
+fn take_settings_snapshot(world: &mut LockWorld) {
+    world.snapshot = Some(world.settings.clone());
+}
+
+#[then(regex = r#"^the changed settings are "(.*)"$"#)]
+fn assert_changed_fields(world: &mut LockWorld, expected: String) {
+    let before = world.snapshot.as_ref().expect("snapshot taken");
+    let expected: Vec<String> = expected.split(", ").map(str::to_string).collect();
+    assert_eq!(changed_fields(before, &world.settings), expected);
+}
+
+fn type_text(world: &mut LockWorld, text: &str) {
+    for unit in text.encode_utf16() {
+        world.input.push_utf16(unit);
+    }
+}
+
+#[when(regex = r#"^the code points "(.*)" are typed$"#)]
+fn type_code_points(world: &mut LockWorld, code_points: String) {
+    let text: String = code_points
+        .split_whitespace()
+        .map(|cp| {
+            let value = u32::from_str_radix(cp.trim_start_matches("U+"), 16).unwrap();
+            char::from_u32(value).unwrap()
+        })
+        .collect();
+    type_text(world, &text);
+}
+
+#[when(regex = r#"^the text "(.*)" is typed$"#)]
+fn type_plain_text(world: &mut LockWorld, text: String) {
+    type_text(world, &text);
+}
+
+#[when("backspace is pressed")]
+fn press_backspace(world: &mut LockWorld) {
+    world.input.backspace();
+}
+
+#[then(regex = r"^the typed input has (\d+) characters$")]
+fn assert_typed_length(world: &mut LockWorld, count: usize) {
+    assert_eq!(world.input.grapheme_count(), count);
+}
+
+#[then("the typed input unlocks")]
+fn assert_typed_input_unlocks(world: &mut LockWorld) {
+    assert!(
+        world
+            .settings
+            .password_verifier()
+            .verify(world.input.normalized().expose())
+    );
+}
+
+#[when("the typed input is cleared")]
+fn clear_typed_input(world: &mut LockWorld) {
+    world.input.clear();
+}
+
+#[then(regex = r#"^the debug output does not contain "(.*)"$"#)]
+fn assert_debug_redacted(world: &mut LockWorld, secret: String) {
+    let debug = format!("{world:?}");
+    assert!(!debug.contains(&secret), "secret leaked into {debug}");
+}
+
+#[when("recovery codes are generated")]
+fn generate_recovery_codes(world: &mut LockWorld) {
+    let codes = regenerate_recovery_codes(&mut world.settings.recovery_codes);
+    world.previous_recovery_codes = std::mem::replace(&mut world.recovery_codes, codes);
+}
+
+fn enter_recovery_code(world: &mut LockWorld, code: &str) {
+    world.recovery_accepted = Some(consume_recovery_code(
+        &mut world.settings.recovery_codes,
+        code,
+    ));
+}
+
+#[when("the first recovery code is entered")]
+fn enter_first_recovery_code(world: &mut LockWorld) {
+    let code = world.recovery_codes[0].clone();
+    enter_recovery_code(world, code.expose());
+}
+
+#[when("the first recovery code is entered in lowercase without dashes")]
+fn enter_first_recovery_code_loosely(world: &mut LockWorld) {
+    let code = world.recovery_codes[0]
+        .expose()
+        .replace('-', "")
+        .to_lowercase();
+    enter_recovery_code(world, &code);
+}
+
+#[when("a code from the previous set is entered")]
+fn enter_previous_recovery_code(world: &mut LockWorld) {
+    let code = world.previous_recovery_codes[0].clone();
+    enter_recovery_code(world, code.expose());
+}
+
+#[then("the recovery code is accepted")]
+fn assert_recovery_accepted(world: &mut LockWorld) {
+    assert_eq!(world.recovery_accepted, Some(true));
+}
+
+#[then("the recovery code is rejected")]
+fn assert_recovery_rejected(world: &mut LockWorld) {
+    assert_eq!(world.recovery_accepted, Some(false));
+}
+
+#[then(regex = r"^(\d+) recovery codes remain$")]
+fn assert_recovery_remaining(world: &mut LockWorld, count: usize) {
+    assert_eq!(
+        remaining_recovery_codes(&world.settings.recovery_codes),
+        count
+    );
+}
+
+#[then("the saved settings do not contain the first recovery code")]
+fn assert_recovery_code_hashed(world: &mut LockWorld) {
+    let json = serde_json::to_string(&world.settings).expect("settings serialize");
+    let code = world.recovery_codes[0].expose();
+    assert!(!json.contains(code) && !json.contains(&code.replace('-', "")));
+}
+
+#[when(regex = r#"^the user sets the duress password "(.*)"$"#)]
+fn set_duress_password(world: &mut LockWorld, password: String) {
+    world.settings.set_duress_password(&password);
+}
+
+#[then("the unlock was under duress")]
+fn assert_under_duress(world: &mut LockWorld) {
+    assert!(
+        world
+            .chain
+            .as_ref()
+            .expect("chain built")
+            .unlocked_under_duress()
+    );
+}
+
+#[then("the unlock was not under duress")]
+fn assert_not_under_duress(world: &mut LockWorld) {
+    assert!(
+        !world
+            .chain
+            .as_ref()
+            .expect("chain built")
+            .unlocked_under_duress()
+    );
+}
+
+#[when("the duress actions write a marker file and an audit entry")]
+fn fire_duress(world: &mut LockWorld) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let marker = log.path().with_file_name("duress.marker");
+    let actions = [
+        DuressAction::MarkerFile {
+            path: marker.clone(),
+        },
+        DuressAction::AuditEntry,
+    ];
+    for result in fire_duress_actions(&actions, log) {
+        result.expect("duress action");
+    }
+    world.marker_path = Some(marker);
+}
+
+#[when("the duress actions run a program that does not exist and write an audit entry")]
+fn run_failing_duress(world: &mut LockWorld) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    let actions = vec![
+        DuressAction::RunCommand {
+            program: log
+                .path()
+                .with_file_name("no-such-program")
+                .display()
+                .to_string(),
+            args: Vec::new(),
+        },
+        DuressAction::AuditEntry,
+    ];
+    run_duress_actions(&actions, log);
+}
+
+#[then(regex = r"^the audit log records (\d+) failed duress actions?$")]
+fn assert_duress_failures_audited(world: &mut LockWorld, count: usize) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    assert_eq!(
+        summarize(&log.read_all().unwrap()).failed_duress_actions,
+        count
+    );
+}
+
+#[then("the duress marker file exists")]
+fn assert_marker_exists(world: &mut LockWorld) {
+    assert!(world.marker_path.as_ref().expect("marker written").exists());
+}
+
+#[then(regex = r"^the audit log records (\d+) duress unlock$")]
+fn assert_duress_audited(world: &mut LockWorld, count: usize) {
+    let log = world.audit.as_ref().expect("audit log configured");
+    assert_eq!(summarize(&log.read_all().unwrap()).duress_unlocks, count);
+}
+
+#[given(regex = r#"^a guest password "(.*)" valid from (-?\d+) hours to (-?\d+) hours from now$"#)]
+fn add_guest_window(world: &mut LockWorld, password: String, from: i64, until: i64) {
+    let now = Local::now();
+    world.settings.guest_passwords.push(GuestPassword::new(
+        &password,
+        &password,
+        Some(now + ChronoDuration::hours(from)),
+        Some(now + ChronoDuration::hours(until)),
+        None,
+    ));
+}
+
+#[given(regex = r#"^a guest password "(.*)" with (\d+) uses? valid for (\d+) hours$"#)]
+fn add_guest_uses(world: &mut LockWorld, password: String, uses: u32, hours: i64) {
+    world.settings.guest_passwords.push(GuestPassword::new(
+        &password,
+        &password,
+        None,
+        Some(Local::now() + ChronoDuration::hours(hours)),
+        Some(uses),
+    ));
+}
+
+#[given(regex = r"^a guest session that expired (\d+) hours ago$")]
+fn add_expired_session(world: &mut LockWorld, hours: i64) {
+    world.settings.guest_session = Some(GuestSession {
+        label: "old".to_string(),
+        expires_at: Some((Local::now() - ChronoDuration::hours(hours)).to_rfc3339()),
+    });
+}
+
+#[when(regex = r#"^the guest password "(.*)" is tried$"#)]
+fn try_guest(world: &mut LockWorld, password: String) {
+    let guests = &mut world.settings.guest_passwords;
+    let matched = consume_guest_password(guests, &password, Local::now());
+    if let Some(index) = matched {
+        world.settings.guest_session = Some(GuestSession::for_guest(&guests[index]));
+    }
+    world.guest_accepted = Some(matched.is_some());
+}
+
+#[when("guest access is pruned")]
+fn prune_guests(world: &mut LockWorld) {
+    world.settings.prune_guest_access(Local::now());
+}
+
+#[then("the guest unlock is accepted")]
+fn assert_guest_accepted(world: &mut LockWorld) {
+    assert_eq!(world.guest_accepted, Some(true));
+}
+
+#[then("the guest unlock is rejected")]
+fn assert_guest_rejected(world: &mut LockWorld) {
+    assert_eq!(world.guest_accepted, Some(false));
+}
+
+#[then(regex = r"^the guest session expires in (\d+) hours$")]
+fn assert_guest_expiry(world: &mut LockWorld, hours: i64) {
+    let session = world
+        .settings
+        .guest_session
+        .as_ref()
+        .expect("guest session");
+    let remaining = session.expires_at().expect("expiry") - Local::now();
+    assert!(
+        (remaining - ChronoDuration::hours(hours))
+            .num_minutes()
+            .abs()
+            <= 1
+    );
+}
+
+#[then(regex = r"^(\d+) guest passwords? remains?$")]
+fn assert_guest_count(world: &mut LockWorld, count: usize) {
+    assert_eq!(world.settings.guest_passwords.len(), count);
+}
+
+#[then("there is no guest session")]
+fn assert_no_guest_session(world: &mut LockWorld) {
+    assert!(world.settings.guest_session.is_none());
+}
+
+#[given("an unlock token key is configured")]
+fn configure_token_key(world: &mut LockWorld) {
+    let (private_key, public_key) = generate_signing_key();
+    world.settings.unlock_token = Some(UnlockTokenConfig {
+        public_key,
+        directory: Some(temp_dir("tokens")),
+    });
+    world.token_private_key = Some(private_key);
+}
+
+#[when(regex = r#"^a token is signed for "(.*)" valid for (-?\d+) seconds$"#)]
+fn sign_token(world: &mut LockWorld, machine: String, seconds: i64) {
+    let private_key = world.token_private_key.as_ref().expect("token key");
+    let key = signing_key_from_hex(private_key.expose()).expect("valid key");
+    let expires = unix_now().saturating_add_signed(seconds);
+    world.token = sign_unlock_token(&key, &machine, expires);
+}
+
+#[when(regex = r#"^a token is signed with a different key for "(.*)"$"#)]
+fn sign_token_with_other_key(world: &mut LockWorld, machine: String) {
+    let (other_key, _) = generate_signing_key();
+    let key = signing_key_from_hex(other_key.expose()).expect("valid key");
+    world.token = sign_unlock_token(&key, &machine, unix_now() + 3600);
+}
+
+#[when("the token expiry is pushed back")]
+fn tamper_token(world: &mut LockWorld) {
+    let mut parts: Vec<String> = world.token.split('.').map(str::to_string).collect();
+    let expires: u64 = parts[1].parse().expect("numeric expiry");
+    parts[1] = (expires + 86_400).to_string();
+    world.token = parts.join(".");
+}
+
+#[when(regex = r#"^the token is presented on "(.*)"$"#)]
+fn present_token(world: &mut LockWorld, machine: String) {
+    let token = world.token.clone();
+    present_token_text(world, &token, &machine);
+}
+
+fn present_token_text(world: &mut LockWorld, text: &str, machine: &str) {
+    let config = world.settings.unlock_token.as_ref().expect("token config");
+    world.token_result = Some(verify_unlock_token(
+        text,
+        config,
+        machine,
+        unix_now(),
+        &mut world.settings.used_token_nonces,
+    ));
+}
+
+#[when("the token is dropped into the token directory")]
+fn drop_token_file(world: &mut LockWorld) {
+    let config = world.settings.unlock_token.as_ref().expect("token config");
+    fs::write(config.directory().join("admin.token"), &world.token).expect("write token");
+}
+
+#[when(regex = r#"^the token directory is read on "(.*)"$"#)]
+fn read_token_directory(world: &mut LockWorld, machine: String) {
+    let config = world.settings.unlock_token.as_ref().expect("token config");
+    let tokens = take_token_files(&config.directory());
+    assert_eq!(tokens.len(), 1);
+    present_token_text(world, &tokens[0], &machine);
+}
+
+#[when(regex = r"^used nonces are pruned (\d+) seconds later$")]
+fn prune_nonces(world: &mut LockWorld, seconds: u64) {
+    prune_used_nonces(&mut world.settings.used_token_nonces, unix_now() + seconds);
+}
+
+#[then("the token is accepted")]
+fn assert_token_accepted(world: &mut LockWorld) {
+    let result = world.token_result.as_ref().expect("token presented");
+    assert!(result.is_ok(), "token rejected: {result:?}");
+}
+
+#[then(regex = r"^the token is rejected as (\w+)$")]
+fn assert_token_rejected(world: &mut LockWorld, reason: String) {
+    match world.token_result.as_ref().expect("token presented") {
+        Err(err) => assert_eq!(format!("{err:?}"), reason),
+        Ok(token) => panic!("token accepted: {token:?}"),
+    }
+}
+
+#[then("the token directory is empty")]
+fn assert_token_directory_empty(world: &mut LockWorld) {
+    let config = world.settings.unlock_token.as_ref().expect("token config");
+    assert_eq!(fs::read_dir(config.directory()).unwrap().count(), 0);
+}
+
+#[then(regex = r"^(\d+) used nonces? remains?$")]
+fn assert_used_nonces(world: &mut LockWorld, count: usize) {
+    assert_eq!(world.settings.used_token_nonces.len(), count);
+}
+
+fn picked_shares(world: &LockWorld, picked: &str) -> Vec<Share> {
+    picked
+        .split(',')
+        .map(|index| {
+            let index: u8 = index.trim().parse().expect("share index");
+            world.shares[index as usize - 1].clone()
+        })
+        .collect()
+}
+
+#[given(regex = r#"^the secret "(.*)" is split into (\d+) shares with threshold (\d+)$"#)]
+fn split_into_shares(world: &mut LockWorld, secret: String, count: u8, threshold: u8) {
+    world.shares = split_secret(secret.as_bytes(), threshold, count).expect("valid split");
+    assert_eq!(world.shares.len(), count as usize);
+}
+
+#[when(regex = r"^shares ([\d,]+) are combined$")]
+fn combine_picked(world: &mut LockWorld, picked: String) {
+    let shares = picked_shares(world, &picked);
+    world.combined = combine_shares(&shares).expect("combinable").to_vec();
+}
+
+#[when(regex = r"^shares ([\d,]+) are encoded, parsed and combined$")]
+fn combine_round_tripped(world: &mut LockWorld, picked: String) {
+    let shares: Vec<Share> = picked_shares(world, &picked)
+        .iter()
+        .map(|share| Share::parse(share.encode().expose()).expect("parsable share"))
+        .collect();
+    world.combined = combine_shares(&shares).expect("combinable").to_vec();
+}
+
+#[then(regex = r#"^the combined secret is "(.*)"$"#)]
+fn assert_combined(world: &mut LockWorld, secret: String) {
+    assert_eq!(world.combined, secret.as_bytes());
+}
+
+#[then(regex = r#"^the combined secret is not "(.*)"$"#)]
+fn assert_not_combined(world: &mut LockWorld, secret: String) {
+    assert_ne!(world.combined, secret.as_bytes());
+}
+
+#[then(regex = r"^splitting into (\d+) shares with threshold (\d+) fails$")]
+fn assert_split_fails(_world: &mut LockWorld, count: u8, threshold: u8) {
+    assert!(split_secret(b"secret", threshold, count).is_none());
+}
+
+#[when(regex = r"^(\d+) of (\d+) unlock shares are enrolled as the only factor$")]
+fn enroll_unlock_shares(world: &mut LockWorld, threshold: u8, count: u8) {
+    world.previous_unlock_shares = std::mem::take(&mut world.unlock_shares);
+    world.unlock_shares = enroll_shares(&mut world.settings, threshold, count).expect("enrolled");
+    world
+        .settings
+        .authenticators
+        .retain(|config| matches!(config, AuthenticatorConfig::Shares { .. }));
+}
+
+#[when(regex = r"^unlock share (\d+) is submitted$")]
+fn submit_unlock_share(world: &mut LockWorld, index: usize) {
+    let share = world.unlock_shares[index - 1].clone();
+    let chain = world.chain.as_mut().expect("chain built");
+    world.outcome = Some(chain.submit(share.expose()));
+}
+
+#[when("a share from the previous enrollment is submitted")]
+fn submit_previous_share(world: &mut LockWorld) {
+    let share = world.previous_unlock_shares[1].clone();
+    let chain = world.chain.as_mut().expect("chain built");
+    world.outcome = Some(chain.submit(share.expose()));
+}
+
+#[given("an empty keyfile directory")]
+fn keyfile_directory(world: &mut LockWorld) {
+    world.keyfile_dir = temp_dir("keyfile");
+}
+
+#[given(regex = r#"^a keyfile for contents "(.*)" is configured at "(.*)"$"#)]
+fn configure_keyfile(world: &mut LockWorld, contents: String, pattern: String) {
+    let pattern = world.keyfile_dir.join(pattern).display().to_string();
+    world.settings.keyfile = Some(KeyfileConfig::new(contents.as_bytes(), vec![pattern]));
+}
+
+#[when(regex = r#"^the file "(.*)" containing "(.*)" is created$"#)]
+fn create_keyfile(world: &mut LockWorld, relative: String, contents: String) {
+    let path = world.keyfile_dir.join(relative);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[when("the keyfile locations are polled")]
+fn poll_keyfile(world: &mut LockWorld) {
+    let config = world.settings.keyfile.as_ref().expect("keyfile configured");
+    world.keyfile_match = find_matching_keyfile(config);
+}
+
+#[then(regex = r#"^the keyfile at "(.*)" matches$"#)]
+fn assert_keyfile_match(world: &mut LockWorld, relative: String) {
+    assert_eq!(
+        world.keyfile_match.as_deref(),
+        Some(world.keyfile_dir.join(relative).as_path())
+    );
+}
+
+#[then(regex = r"^the found keyfile (still matches|no longer matches)$")]
+fn assert_found_keyfile_rechecked(world: &mut LockWorld, outcome: String) {
+    let config = world.settings.keyfile.as_ref().expect("keyfile configured");
+    let path = world.keyfile_match.as_ref().expect("a keyfile was found");
+    assert_eq!(
+        is_matching_keyfile(config, path),
+        outcome == "still matches"
+    );
+}
+
+#[then("no keyfile matches")]
+fn assert_no_keyfile_match(world: &mut LockWorld) {
+    assert_eq!(world.keyfile_match, None);
+}
+
+#[then(regex = r#"^the pattern "(.*)" matches "(.*)": (yes|no)$"#)]
+fn assert_wildcard(_world: &mut LockWorld, pattern: String, name: String, expected: String) {
+    assert_eq!(wildcard_match(&pattern, &name), expected == "yes");
+}
+
+fn challenge_config(world: &LockWorld) -> &ChallengeConfig {
+    world
+        .settings
+        .challenge
+        .as_ref()
+        .expect("challenge configured")
+}
+
+fn issued_challenge(world: &LockWorld) -> &Challenge {
+    world.challenge.as_ref().expect("challenge issued")
+}
+
+fn helpdesk_response(world: &LockWorld) -> String {
+    challenge_config(world)
+        .response(&issued_challenge(world).code)
+        .expect("valid secret")
+}
+
+#[given(regex = r"^a challenge secret shown after (\d+) failures and valid for (\d+) seconds$")]
+fn configure_challenge(world: &mut LockWorld, after_failures: u32, expiry_secs: u64) {
+    world.settings.challenge = Some(ChallengeConfig {
+        after_failures,
+        expiry_secs,
+        ..ChallengeConfig::generate()
+    });
+}
+
+#[when("a challenge is issued")]
+fn issue_challenge(world: &mut LockWorld) {
+    world.challenge = Some(Challenge::new(world.now));
+}
+
+#[then(regex = r#"^the helpdesk response looks like "(.*)"$"#)]
+fn assert_response_shape(world: &mut LockWorld, shape: String) {
+    let response = helpdesk_response(world);
+    assert_eq!(response.len(), shape.len());
+    for (c, expected) in response.chars().zip(shape.chars()) {
+        match expected {
+            'd' => assert!(c.is_ascii_digit(), "{response}"),
+            other => assert_eq!(c, other),
+        }
+    }
+}
+
+#[then("the helpdesk response is accepted")]
+fn assert_response_accepted(world: &mut LockWorld) {
+    let response = helpdesk_response(world);
+    assert!(verify_response(
+        challenge_config(world),
+        issued_challenge(world),
+        &response
+    ));
+}
+
+#[then("the helpdesk response without the dash is accepted")]
+fn assert_undashed_response_accepted(world: &mut LockWorld) {
+    let response = helpdesk_response(world).replace('-', "");
+    assert!(verify_response(
+        challenge_config(world),
+        issued_challenge(world),
+        &response
+    ));
+}
+
+#[then(regex = r#"^the response "(.*)" is refused$"#)]
+fn assert_response_refused(world: &mut LockWorld, response: String) {
+    assert!(!verify_response(
+        challenge_config(world),
+        issued_challenge(world),
+        &response
+    ));
+}
+
+#[then("the response for another challenge is refused")]
+fn assert_other_challenge_refused(world: &mut LockWorld) {
+    let other = Challenge::new(world.now);
+    let response = challenge_config(world)
+        .response(&other.code)
+        .expect("valid secret");
+    assert!(!verify_response(
+        challenge_config(world),
+        issued_challenge(world),
+        &response
+    ));
+}
+
+#[then("a response computed with another secret is refused")]
+fn assert_other_secret_refused(world: &mut LockWorld) {
+    let response = ChallengeConfig::generate()
+        .response(&issued_challenge(world).code)
+        .expect("valid secret");
+    assert!(!verify_response(
+        challenge_config(world),
+        issued_challenge(world),
+        &response
+    ));
+}
+
+#[then(regex = r"^a challenge is (not )?due after (\d+) failures$")]
+fn assert_challenge_due(world: &mut LockWorld, negated: String, failures: u32) {
+    let due = challenge_due(
+        challenge_config(world),
+        failures,
+        world.challenge.as_ref(),
+        world.now,
+    );
+    assert_eq!(due, negated.is_empty());
+}
+
+#[then("the challenge has expired")]
+fn assert_challenge_expired(world: &mut LockWorld) {
+    let expiry = challenge_config(world).expiry();
+    assert!(issued_challenge(world).is_expired(expiry, world.now));
+}
+
+fn violation_name(violation: &PolicyViolation) -> String {
+    format!("{violation:?}")
+        .split([' ', '{'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[given(regex = r"^the policy requires (\d+) character classes$")]
+fn require_classes(world: &mut LockWorld, classes: usize) {
+    world.settings.password_policy.min_character_classes = classes;
+}
+
+#[given(regex = r"^the policy requires at least (\d+) characters$")]
+fn require_length(world: &mut LockWorld, length: usize) {
+    world.settings.password_policy.min_length = length;
+}
+
+#[given(regex = r"^the policy limits password age to (\d+) days$")]
+fn limit_age(world: &mut LockWorld, days: u32) {
+    world.settings.password_policy.max_age_days = Some(days);
+}
+
+#[when("the policy allows the default password")]
+fn allow_default_password(world: &mut LockWorld) {
+    world.settings.password_policy.allow_default_password = true;
+}
+
+#[when(regex = r"^the password was changed (\d+) days ago$")]
+fn backdate_password(world: &mut LockWorld, days: i64) {
+    world.settings.password_changed_at =
+        Some((Local::now() - ChronoDuration::days(days)).to_rfc3339());
+}
+
+#[when(regex = r#"^the password change time is recorded as "(.*)"$"#)]
+fn record_password_change_time(world: &mut LockWorld, changed_at: String) {
+    world.settings.password_changed_at = Some(changed_at);
+}
+
+#[when(regex = r#"^the user tries to change the password to "(.*)"$"#)]
+fn change_password(world: &mut LockWorld, password: String) {
+    world.password_change = Some(world.settings.change_password(&password));
+}
+
+#[then(regex = r#"^the password "(.*)" is (allowed|refused as (\w+))$"#)]
+fn assert_password_verdict(world: &mut LockWorld, password: String, verdict: String) {
+    let result = world.settings.password_policy.check_password(&password);
+    match verdict.strip_prefix("refused as ") {
+        Some(reason) => assert_eq!(
+            result.map_err(|v| violation_name(&v)),
+            Err(reason.to_string())
+        ),
+        None => assert_eq!(result, Ok(())),
+    }
+}
+
+#[then("the password change is accepted")]
+fn assert_change_accepted(world: &mut LockWorld) {
+    assert_eq!(world.password_change, Some(Ok(())));
+}
+
+#[then(regex = r"^the password change is refused as (\w+)$")]
+fn assert_change_refused(world: &mut LockWorld, reason: String) {
+    let result = world.password_change.expect("password change attempted");
+    assert_eq!(result.map_err(|v| violation_name(&v)), Err(reason));
+}
+
+#[then("locking is allowed")]
+fn assert_lock_allowed(world: &mut LockWorld) {
+    assert_eq!(world.settings.lock_policy_violation(Local::now()), None);
+}
+
+#[then(regex = r"^locking is refused as (\w+)$")]
+fn assert_lock_refused(world: &mut LockWorld, reason: String) {
+    let violation = world.settings.lock_policy_violation(Local::now());
+    assert_eq!(violation.map(|v| violation_name(&v)), Some(reason));
+}
+
+fn settings_fixture(name: &str) -> Vec<u8> {
+    fs::read(PathBuf::from("tests/fixtures/settings").join(name)).expect("fixture exists")
+}
+
+#[when(regex = r#"^the settings fixture "(.*)" is migrated$"#)]
+fn migrate_fixture(world: &mut LockWorld, name: String) {
+    let mut value: serde_json::Value = serde_json::from_slice(&settings_fixture(&name)).unwrap();
+    world.upgraded = Some(migrate_settings_value(&mut value).expect("migratable fixture"));
+    world.migrated_value = Some(value);
+}
+
+#[when(regex = r#"^the settings fixture "(.*)" is parsed$"#)]
+fn parse_fixture(world: &mut LockWorld, name: String) {
+    world.parsed = Some(parse_settings(&settings_fixture(&name)).map(|(settings, _)| settings));
+}
+
+#[then(regex = r#"^it matches the fixture "(.*)"$"#)]
+fn assert_matches_fixture(world: &mut LockWorld, name: String) {
+    let expected: serde_json::Value = serde_json::from_slice(&settings_fixture(&name)).unwrap();
+    assert_eq!(world.migrated_value.as_ref(), Some(&expected));
+}
+
+#[then(regex = r"^the fixture was (not )?upgraded$")]
+fn assert_upgraded(world: &mut LockWorld, negated: String) {
+    assert_eq!(world.upgraded, Some(negated.is_empty()));
+}
+
+#[then("the parsed settings open on startup")]
+fn assert_parsed_open_on_startup(world: &mut LockWorld) {
+    let settings = world.parsed.as_ref().unwrap().as_ref().expect("parsed");
+    assert!(settings.open_settings_on_startup);
+}
+
+#[then("the parsed settings are at the current schema version")]
+fn assert_parsed_current_version(world: &mut LockWorld) {
+    let settings = world.parsed.as_ref().unwrap().as_ref().expect("parsed");
+    assert_eq!(settings.schema_version, CURRENT_SCHEMA_VERSION);
+}
+
+#[then(regex = r"^the settings are refused as written by schema version (\d+)$")]
+fn assert_newer_version_refused(world: &mut LockWorld, version: u64) {
+    match world.parsed.as_ref().expect("parsed") {
+        Err(SettingsError::Schema(MigrationError::NewerVersion { found, .. })) => {
+            assert_eq!(*found, version)
+        }
+        other => panic!("expected a newer-version error, got {other:?}"),
+    }
+}
+
+#[given(regex = r#"^a settings file copied from the fixture "(.*)"$"#)]
+fn settings_file_from_fixture(world: &mut LockWorld, name: String) {
+    world.settings_original = settings_fixture(&name);
+    world.settings_file = temp_dir("settings").join("dwmlock_settings.json");
+    fs::write(&world.settings_file, &world.settings_original).unwrap();
+}
+
+#[given("no settings file")]
+fn no_settings_file(world: &mut LockWorld) {
+    world.settings_file = temp_dir("settings").join("dwmlock_settings.json");
+}
+
+#[when("the settings file is loaded")]
+fn load_settings_file(world: &mut LockWorld) {
+    world.parsed = Some(load_settings_from(&world.settings_file));
+}
+
+#[when("the settings file is read without writing")]
+fn read_settings_file(world: &mut LockWorld) {
+    world.parsed = Some(read_settings_from(&world.settings_file));
+}
+
+#[when("the settings file is checked")]
+fn check_settings(world: &mut LockWorld) {
+    world.settings_check = Some(check_settings_file(&world.settings_file));
+}
+
+fn assert_syntax_error_at(err: Option<&SettingsError>, expected: (usize, usize)) {
+    match err {
+        Some(SettingsError::Syntax { line, column, .. }) => {
+            assert_eq!((*line, *column), expected)
+        }
+        other => panic!("expected a syntax error, got {other:?}"),
+    }
+}
+
+#[then(regex = r"^loading fails at line (\d+) column (\d+)$")]
+fn assert_load_fails_at(world: &mut LockWorld, line: usize, column: usize) {
+    let result = world.parsed.as_ref().expect("loaded");
+    assert_syntax_error_at(result.as_ref().err(), (line, column));
+}
+
+#[then(regex = r"^the check fails at line (\d+) column (\d+)$")]
+fn assert_check_fails_at(world: &mut LockWorld, line: usize, column: usize) {
+    let result = world.settings_check.as_ref().expect("checked");
+    assert_syntax_error_at(result.as_ref().err(), (line, column));
+}
+
+#[when(regex = r"^settings with a blur radius of (\d+) are saved to the settings file$")]
+fn save_settings_file(world: &mut LockWorld, radius: usize) {
+    let settings = Settings {
+        blur_radius: radius,
+        ..Settings::default()
+    };
+    world.settings_save = Some(persist_settings_to(&world.settings_file, &settings));
+}
+
+#[then(regex = r"^the settings file has a blur radius of (\d+)$")]
+fn assert_saved_blur_radius(world: &mut LockWorld, radius: usize) {
+    assert!(matches!(world.settings_save, Some(Ok(()))));
+    let (settings, _) = parse_settings(&fs::read(&world.settings_file).unwrap()).unwrap();
+    assert_eq!(settings.blur_radius, radius);
+}
+
+fn settings_sibling(world: &LockWorld, suffix: &str) -> PathBuf {
+    let mut name = world.settings_file.file_name().unwrap().to_os_string();
+    name.push(suffix);
+    world.settings_file.with_file_name(name)
+}
+
+#[then("the previous settings file is kept as a backup")]
+fn assert_previous_kept(world: &mut LockWorld) {
+    let previous = fs::read(settings_sibling(world, ".prev")).expect("backup written");
+    assert_eq!(previous, world.settings_original);
+}
+
+#[then("no temporary settings file is left behind")]
+fn assert_no_temp_file(world: &mut LockWorld) {
+    let directory = world.settings_file.parent().unwrap();
+    let leftovers: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "left behind: {leftovers:?}");
+}
+
+#[given("a directory is in the way of the settings backup")]
+fn block_settings_backup(world: &mut LockWorld) {
+    fs::create_dir_all(settings_sibling(world, ".prev")).unwrap();
+}
+
+#[then(regex = r"^saving is refused as written by schema version (\d+)$")]
+fn assert_save_refused(world: &mut LockWorld, version: u64) {
+    match world.settings_save.as_ref().expect("saved") {
+        Err(SettingsError::Schema(MigrationError::NewerVersion { found, .. })) => {
+            assert_eq!(*found, version)
+        }
+        other => panic!("expected a newer-version error, got {other:?}"),
+    }
+}
+
+#[then("the check passes")]
+fn assert_check_passes(world: &mut LockWorld) {
+    assert!(matches!(world.settings_check, Some(Ok(()))));
+}
+
+#[then("the settings file is unchanged")]
+fn assert_settings_file_unchanged(world: &mut LockWorld) {
+    assert_eq!(
+        fs::read(&world.settings_file).unwrap(),
+        world.settings_original
+    );
+}
+
+#[then(regex = r"^the settings file (exists|does not exist)$")]
+fn assert_settings_file_exists(world: &mut LockWorld, state: String) {
+    assert_eq!(world.settings_file.exists(), state == "exists");
+}
+
+fn settings_backups(world: &LockWorld) -> Vec<PathBuf> {
+    fs::read_dir(world.settings_file.parent().unwrap())
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bak"))
+        .collect()
+}
+
+#[then("a timestamped backup of the settings file exists")]
+fn assert_backup_exists(world: &mut LockWorld) {
+    let backups = settings_backups(world);
+    assert_eq!(backups.len(), 1);
+    assert_eq!(fs::read(&backups[0]).unwrap(), world.settings_original);
+    match world.parsed.as_ref().expect("loaded") {
+        Err(SettingsError::Syntax { backup, .. }) => assert_eq!(backup.as_ref(), Some(&backups[0])),
+        other => panic!("expected a syntax error, got {other:?}"),
+    }
+}
+
+#[then("no backup of the settings file exists")]
+fn assert_no_backup(world: &mut LockWorld) {
+    assert!(settings_backups(world).is_empty());
+}
+
+fn json_value(text: &str) -> serde_json::Value {
+    serde_json::from_str(text).expect("valid JSON in the feature file")
+}
+
+fn value_at<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    key.split('.')
+        .try_fold(value, |current, part| current.get(part))
+}
+
+fn resolved_settings(world: &LockWorld) -> &Settings {
+    world
+        .resolved
+        .as_ref()
+        .expect("resolved")
+        .as_ref()
+        .expect("layers resolve")
+}
+
+#[given(regex = r#"^the user's settings set "(.*)" to (.*)$"#)]
+fn user_setting(world: &mut LockWorld, key: String, value: String) {
+    let mut settings = serde_json::to_value(&world.settings).unwrap();
+    settings[key.as_str()] = json_value(&value);
+    world.settings = serde_json::from_value(settings).unwrap();
+}
+
+#[given(regex = r#"^the machine policy sets "(.*)" to (.*)$"#)]
+fn policy_setting(world: &mut LockWorld, key: String, value: String) {
+    let mut current = world.policy_file.get_or_insert_with(|| json_value("{}"));
+    for part in key.split('.') {
+        current = &mut current[part];
+    }
+    *current = json_value(&value);
+}
+
+#[given(regex = r#"^the environment sets "(.*)" to "(.*)"$"#)]
+fn environment_variable(world: &mut LockWorld, name: String, value: String) {
+    world.environment.push((name, value));
+}
+
+#[given(regex = r#"^the command line is "(.*)"$"#)]
+fn command_line(world: &mut LockWorld, line: String) {
+    world.command_line = line.split_whitespace().map(str::to_string).collect();
+}
+
+#[when("the settings layers are resolved")]
+fn resolve_layers(world: &mut LockWorld) {
+    let global = parse_args(&world.command_line)
+        .map(|invocation| invocation.global)
+        .map_err(|err| SettingsError::Layer {
+            layer: ConfigLayer::CommandLine,
+            message: err.message,
+        });
+    world.resolved = Some(global.and_then(|global| {
+        resolve_settings(
+            world.settings.clone(),
+            global.profile.as_deref(),
+            world.policy_file.clone(),
+            environment_overrides(world.environment.clone()),
+            global.overrides,
+        )
+    }));
+}
+
+#[when(regex = r#"^the resolved monitor mode is changed to "(.*)"$"#)]
+fn change_resolved_monitor_mode(world: &mut LockWorld, mode: String) {
+    let mode = serde_json::from_value(serde_json::Value::String(mode)).unwrap();
+    if let Some(Ok(settings)) = world.resolved.as_mut() {
+        settings.monitor_mode = mode;
+    }
+}
+
+#[when("the resolved settings are saved to a settings file")]
+fn save_resolved(world: &mut LockWorld) {
+    world.settings_file = temp_dir("layers").join("dwmlock_settings.json");
+    persist_settings_to(&world.settings_file, resolved_settings(world)).expect("saved");
+}
+
+#[then(regex = r#"^the setting "(.*)" has the value (.*)$"#)]
+fn assert_resolved_value(world: &mut LockWorld, key: String, value: String) {
+    let resolved = serde_json::to_value(resolved_settings(world)).unwrap();
+    assert_eq!(value_at(&resolved, &key), Some(&json_value(&value)));
+}
+
+#[then(regex = r#"^the setting "(.*)" comes from (.*)$"#)]
+fn assert_value_origin(world: &mut LockWorld, key: String, layer: String) {
+    assert_eq!(
+        resolved_settings(world).value_origin(&key).to_string(),
+        layer
+    );
+}
+
+#[then(regex = r#"^the setting "(.*)" is (not )?locked$"#)]
+fn assert_locked(world: &mut LockWorld, key: String, negated: String) {
+    assert_eq!(resolved_settings(world).is_locked(&key), negated.is_empty());
+}
+
+#[then(regex = r#"^the saved settings have "(.*)" set to (.*)$"#)]
+fn assert_saved_value(world: &mut LockWorld, key: String, value: String) {
+    let saved: serde_json::Value =
+        serde_json::from_slice(&fs::read(&world.settings_file).unwrap()).unwrap();
+    assert_eq!(value_at(&saved, &key), Some(&json_value(&value)));
+}
+
+#[then(regex = r"^resolving fails because of (.*)$")]
+fn assert_layer_error(world: &mut LockWorld, expected: String) {
+    match world.resolved.as_ref().expect("resolved") {
+        Err(SettingsError::Layer { layer, .. }) => assert_eq!(layer.to_string(), expected),
+        other => panic!("expected a layer error, got {other:?}"),
+    }
+}
+
+fn settings_on_disk(world: &mut LockWorld) -> &mut Settings {
+    world.on_disk.get_or_insert_with(|| world.settings.clone())
+}
+
+#[when(regex = r#"^the settings on disk set "(.*)" to (.*)$"#)]
+fn change_setting_on_disk(world: &mut LockWorld, key: String, value: String) {
+    let disk = settings_on_disk(world);
+    let mut edited = serde_json::to_value(&*disk).unwrap();
+    edited[key.as_str()] = json_value(&value);
+    *disk = serde_json::from_value(edited).unwrap();
+}
+
+#[when(regex = r#"^the password on disk is changed to "(.*)"$"#)]
+fn change_password_on_disk(world: &mut LockWorld, password: String) {
+    settings_on_disk(world).set_password(&password);
+}
+
+#[when("a challenge secret is configured on disk")]
+fn challenge_on_disk(world: &mut LockWorld) {
+    settings_on_disk(world).challenge = Some(ChallengeConfig::generate());
+}
+
+#[when("the settings reload is planned")]
+fn plan_settings_reload(world: &mut LockWorld) {
+    let incoming = settings_on_disk(world).clone();
+    let plan = plan_reload(&world.settings, &incoming);
+    world.reloaded = Some(apply_reload(&world.settings, &incoming, &plan));
+    world.reload_plan = Some(plan);
+}
+
+fn field_list(fields: &[String]) -> String {
+    fields.join(", ")
+}
+
+#[then(regex = r#"^the reload applies "(.*)"$"#)]
+fn assert_reload_applies(world: &mut LockWorld, fields: String) {
+    assert_eq!(
+        field_list(&world.reload_plan.as_ref().unwrap().applied),
+        fields
+    );
+}
+
+#[then(regex = r#"^the reload defers "(.*)"$"#)]
+fn assert_reload_defers(world: &mut LockWorld, fields: String) {
+    assert_eq!(
+        field_list(&world.reload_plan.as_ref().unwrap().deferred),
+        fields
+    );
+}
+
+#[then("the reload defers nothing")]
+fn assert_reload_defers_nothing(world: &mut LockWorld) {
+    assert!(world.reload_plan.as_ref().unwrap().deferred.is_empty());
+}
+
+#[then("the reload is empty")]
+fn assert_reload_empty(world: &mut LockWorld) {
+    assert!(world.reload_plan.as_ref().unwrap().is_empty());
+}
+
+#[then(regex = r#"^the reloaded settings have "(.*)" set to (.*)$"#)]
+fn assert_reloaded_value(world: &mut LockWorld, key: String, value: String) {
+    let reloaded = serde_json::to_value(world.reloaded.as_ref().unwrap()).unwrap();
+    assert_eq!(value_at(&reloaded, &key), Some(&json_value(&value)));
+}
+
+#[then("the reloaded settings still accept the default password")]
+fn assert_reloaded_password(world: &mut LockWorld) {
+    let verifier = world.reloaded.as_ref().unwrap().password_verifier();
+    assert!(verifier.verify(DEFAULT_PASSWORD));
+}
+
+#[then("the reloaded settings have no challenge configured")]
+fn assert_reloaded_no_challenge(world: &mut LockWorld) {
+    assert!(world.reloaded.as_ref().unwrap().challenge.is_none());
+}
+
+#[given(regex = r#"^the profile "(.*)" sets "(.*)" to (.*)$"#)]
+fn profile_setting(world: &mut LockWorld, name: String, key: String, value: String) {
+    let profile = world.settings.profiles.entry(name).or_default();
+    let mut edited = serde_json::to_value(&*profile).unwrap();
+    edited[key.as_str()] = json_value(&value);
+    *profile = serde_json::from_value(edited).unwrap();
+}
+
+#[given(regex = r#"^the default profile is "(.*)"$"#)]
+fn default_profile(world: &mut LockWorld, name: String) {
+    world.settings.default_profile = Some(name);
+}
+
+#[when(regex = r#"^the profile "(.*)" is resolved$"#)]
+fn resolve_named_profile(world: &mut LockWorld, name: String) {
+    world.resolved = Some(resolve_profile(world.settings.clone(), Some(&name)));
+}
+
+#[when("no profile is resolved")]
+fn resolve_no_profile(world: &mut LockWorld) {
+    world.resolved = Some(resolve_profile(world.settings.clone(), None));
+}
+
+#[then(regex = r#"^the active profile is "(.*)"$"#)]
+fn assert_active_profile(world: &mut LockWorld, name: String) {
+    assert_eq!(
+        resolved_settings(world).layers.active_profile(),
+        Some(name.as_str())
+    );
+}
+
+#[then("no profile is active")]
+fn assert_no_active_profile(world: &mut LockWorld) {
+    assert_eq!(resolved_settings(world).layers.active_profile(), None);
+}
+
+#[then(regex = r#"^the saved settings have no "(.*)"$"#)]
+fn assert_saved_missing(world: &mut LockWorld, key: String) {
+    let saved: serde_json::Value =
+        serde_json::from_slice(&fs::read(&world.settings_file).unwrap()).unwrap();
+    assert_eq!(value_at(&saved, &key), None);
+}
+
+fn parsed_command(world: &LockWorld) -> &Invocation {
+    world
+        .invocation
+        .as_ref()
+        .expect("parsed")
+        .as_ref()
+        .expect("the arguments parse")
+}
+
+fn help_text(topic: &str) -> &'static str {
+    match topic {
+        "main" => MAIN_HELP,
+        "lock" => LOCK_HELP,
+        "settings" => SETTINGS_HELP,
+        "config" => CONFIG_HELP,
+        "hash-password" => HASH_PASSWORD_HELP,
+        "token" => TOKEN_HELP,
+        "respond" => RESPOND_HELP,
+        other => panic!("no help topic {other:?}"),
+    }
+}
+
+#[when(regex = r#"^the arguments "(.*)" are parsed$"#)]
+fn parse_arguments(world: &mut LockWorld, line: String) {
+    let args: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+    world.invocation = Some(parse_args(&args));
+}
+
+#[then(regex = r"^the command locks (with|without) confirmation$")]
+fn assert_lock_command(world: &mut LockWorld, confirmation: String) {
+    match &parsed_command(world).command {
+        Command::Lock { yes, .. } => assert_eq!(*yes, confirmation == "without"),
+        other => panic!("expected lock, got {other:?}"),
+    }
+}
+
+#[then(regex = r"^the command (opens|does not open) the settings dialog first$")]
+fn assert_lock_opens_settings(world: &mut LockWorld, opens: String) {
+    match &parsed_command(world).command {
+        Command::Lock { open_settings, .. } => assert_eq!(*open_settings, opens == "opens"),
+        other => panic!("expected lock, got {other:?}"),
+    }
+}
+
+#[then(regex = r#"^the command reads the setting "(.*)"$"#)]
+fn assert_get_command(world: &mut LockWorld, expected: String) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::SettingsGet { key: expected }
+    );
+}
+
+#[then(regex = r#"^the command stores (.*) at "(.*)"$"#)]
+fn assert_set_command(world: &mut LockWorld, value: String, key: String) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::SettingsSet {
+            key,
+            value: json_value(&value),
+        }
+    );
+}
+
+#[then(regex = r#"^the command resets "(.*)"$"#)]
+fn assert_reset_command(world: &mut LockWorld, key: String) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::SettingsReset { key: Some(key) }
+    );
+}
+
+#[then("the command lists the settings")]
+fn assert_list_command(world: &mut LockWorld) {
+    assert_eq!(parsed_command(world).command, Command::SettingsList);
+}
+
+#[then("the command checks the settings file")]
+fn assert_check_command(world: &mut LockWorld) {
+    assert_eq!(parsed_command(world).command, Command::ConfigCheck);
+}
+
+#[then("the command prints the version")]
+fn assert_version_command(world: &mut LockWorld) {
+    assert_eq!(parsed_command(world).command, Command::Version);
+}
+
+#[then("the command hashes a password read from standard input")]
+fn assert_hash_from_stdin(world: &mut LockWorld) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::HashPassword { password: None }
+    );
+}
+
+#[then(regex = r"^the command prints the (.*) help$")]
+fn assert_help_command(world: &mut LockWorld, topic: String) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::Help(help_text(&topic))
+    );
+}
+
+#[then(regex = r"^the command generates (\d+) shares with a threshold of (\d+)$")]
+fn assert_shares_command(world: &mut LockWorld, count: u8, threshold: u8) {
+    assert_eq!(
+        parsed_command(world).command,
+        Command::GenerateShares { threshold, count }
+    );
+}
+
+#[then(regex = r#"^the command adds a guest password "(.*)" for (.*) hours$"#)]
+fn assert_guest_command(world: &mut LockWorld, expected: String, expected_hours: f64) {
+    match &parsed_command(world).command {
+        Command::AddGuestPassword { label, hours, .. } => {
+            assert_eq!(label, &expected);
+            assert_eq!(*hours, expected_hours);
+        }
+        other => panic!("expected a guest password, got {other:?}"),
+    }
+}
+
+#[then(regex = r#"^the profile "(.*)" is requested$"#)]
+fn assert_requested_profile(world: &mut LockWorld, name: String) {
+    assert_eq!(parsed_command(world).global.profile, Some(name));
+}
+
+#[then(regex = r#"^the override "(.*)" is (.*)$"#)]
+fn assert_override(world: &mut LockWorld, key: String, value: String) {
+    assert!(
+        parsed_command(world)
+            .global
+            .overrides
+            .contains(&(key, json_value(&value)))
+    );
+}
+
+#[then(regex = r"^the arguments are rejected with the (.*) help$")]
+fn assert_usage_error(world: &mut LockWorld, topic: String) {
+    match world.invocation.as_ref().expect("parsed") {
+        Err(err) => {
+            assert_eq!(err.help, help_text(&topic));
+            assert!(err.to_string().ends_with(err.help));
+        }
+        Ok(invocation) => panic!("expected a usage error, got {invocation:?}"),
+    }
+}
+
+#[then(regex = r"^usage errors exit with status (\d+)$")]
+fn assert_usage_status(_world: &mut LockWorld, status: i32) {
+    assert_eq!(EXIT_USAGE, status);
+}
+
+#[when(regex = r#"^the setting "(.*)" is read$"#)]
+fn read_setting(world: &mut LockWorld, key: String) {
+    world.setting_read = Some(get_setting(&world.settings, &key));
+}
+
+fn shown_value(text: &str) -> serde_json::Value {
+    if text == "hidden" {
+        serde_json::Value::String(HIDDEN_VALUE.to_string())
+    } else {
+        json_value(text)
+    }
+}
+
+#[then(regex = r"^the value read is (.*)$")]
+fn assert_read_value(world: &mut LockWorld, value: String) {
+    assert_eq!(
+        world.setting_read.clone().expect("read"),
+        Some(shown_value(&value))
+    );
+}
+
+#[then("nothing is read")]
+fn assert_nothing_read(world: &mut LockWorld) {
+    assert_eq!(world.setting_read.clone().expect("read"), None);
+}
+
+#[when("the settings are listed")]
+fn list_all_settings(world: &mut LockWorld) {
+    world.listing = list_settings(&world.settings);
+}
+
+#[then(regex = r#"^the listing shows "(.*)" as (.*)$"#)]
+fn assert_listed_value(world: &mut LockWorld, key: String, value: String) {
+    let expected = shown_value(&value);
+    let entry = world.listing.iter().find(|(listed, ..)| listed == &key);
+    assert_eq!(entry.map(|(_, value, _)| value), Some(&expected));
+}
+
+#[then("the listing contains no secret")]
+fn assert_listing_redacted(world: &mut LockWorld) {
+    let text = serde_json::to_string(
+        &world
+            .listing
+            .iter()
+            .map(|(_, value, _)| value)
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    let hash = &world
+        .settings
+        .password_hash
+        .as_ref()
+        .expect("password")
+        .hash;
+    assert!(!text.contains(hash.as_str()));
+}
+
+#[when(regex = r#"^the setting "(.*)" is set to (.*)$"#)]
+fn edit_setting(world: &mut LockWorld, key: String, value: String) {
+    world.resolved = Some(set_setting(&world.settings, &key, json_value(&value)));
+}
+
+#[when(regex = r#"^the setting "(.*)" is reset$"#)]
+fn reset_one_setting(world: &mut LockWorld, key: String) {
+    world.resolved = Some(reset_setting(&world.settings, Some(&key)));
+}
+
+#[when("every setting is reset")]
+fn reset_all_settings(world: &mut LockWorld) {
+    world.resolved = Some(reset_setting(&world.settings, None));
+}
+
+#[then(regex = r#"^the password "(.*)" is still set$"#)]
+fn assert_password_kept(world: &mut LockWorld, password: String) {
+    assert!(
+        resolved_settings(world)
+            .password_verifier()
+            .verify(&password)
+    );
+}
+
+fn schema_property(key: &str) -> serde_json::Value {
+    settings_schema()["properties"][key].clone()
+}
+
+#[then("the published settings schema is up to date")]
+fn assert_schema_published(_world: &mut LockWorld) {
+    let published = fs::read_to_string(SCHEMA_PATH).unwrap_or_default();
+    assert!(
+        published == settings_schema_json(),
+        "{SCHEMA_PATH} is out of date; regenerate it with `dwmlock schema > {SCHEMA_PATH}`"
+    );
+}
+
+#[then(regex = r#"^the schema allows "(.*)" to be (.*)$"#)]
+fn assert_schema_choices(_world: &mut LockWorld, key: String, choices: String) {
+    let schema = settings_schema();
+    let reference = schema["properties"][key.as_str()]["$ref"]
+        .as_str()
+        .expect("a reference to the enum");
+    let name = reference.trim_start_matches("#/$defs/");
+    let allowed: Vec<String> = schema["$defs"][name]["oneOf"]
+        .as_array()
+        .expect("one schema per variant")
+        .iter()
+        .map(|variant| format!("\"{}\"", variant["const"].as_str().unwrap()))
+        .collect();
+    let expected: Vec<String> = choices
+        .replace(" or ", ", ")
+        .split(", ")
+        .map(str::to_string)
+        .collect();
+    assert_eq!(allowed, expected);
+}
+
+#[then(regex = r#"^the schema limits "(.*)" to (\d+) through (\d+)$"#)]
+fn assert_schema_range(_world: &mut LockWorld, key: String, minimum: u64, maximum: u64) {
+    let property = schema_property(&key);
+    assert_eq!(property["minimum"], minimum);
+    assert_eq!(property["maximum"], maximum);
+}
+
+#[then("every default in the schema matches the built-in settings")]
+fn assert_schema_defaults(_world: &mut LockWorld) {
+    let defaults = serde_json::to_value(Settings::default()).unwrap();
+    let schema = settings_schema();
+    let properties = schema["properties"].as_object().unwrap();
+    let with_defaults: Vec<_> = properties
+        .iter()
+        .filter_map(|(key, property)| Some((key, property.get("default")?)))
+        .collect();
+    assert!(!with_defaults.is_empty());
+    for (key, default) in with_defaults {
+        assert_eq!(defaults.get(key), Some(default), "default of {key}");
+    }
+}
+
+#[then("every setting in the schema has a description")]
+fn assert_schema_descriptions(_world: &mut LockWorld) {
+    let schema = settings_schema();
+    for (key, property) in schema["properties"].as_object().unwrap() {
+        assert!(
+            property.get("description").is_some(),
+            "{key} has no description"
+        );
+    }
+}
+
+fn exported_bundle(world: &LockWorld) -> &SettingsBundle {
+    world.bundle.as_ref().expect("a bundle")
+}
+
+fn import_plan(world: &LockWorld) -> &ImportPlan {
+    world
+        .import
+        .as_ref()
+        .expect("imported")
+        .as_ref()
+        .expect("the bundle imports")
+}
+
+#[when("the settings are exported")]
+fn export_settings(world: &mut LockWorld) {
+    world.bundle = Some(export_bundle(&world.settings));
+}
+
+#[when("the bundle is written to a file and read back")]
+fn bundle_round_trip(world: &mut LockWorld) {
+    let path = temp_dir("bundle").join("bundle.json");
+    write_bundle(&path, exported_bundle(world)).expect("written");
+    world.bundle = Some(read_bundle(&path).expect("read back"));
+}
+
+#[given(regex = r"^a bundle with the settings (.*)$")]
+fn bundle_with_settings(world: &mut LockWorld, settings: String) {
+    world.bundle = Some(SettingsBundle {
+        bundle_version: BUNDLE_VERSION,
+        exported_at: String::new(),
+        settings: json_value(&settings),
+    });
+}
+
+#[then(regex = r#"^the bundle has "(.*)" set to (.*)$"#)]
+fn assert_bundle_value(world: &mut LockWorld, key: String, value: String) {
+    assert_eq!(
+        value_at(&exported_bundle(world).settings, &key),
+        Some(&json_value(&value))
+    );
+}
+
+#[then(regex = r#"^the bundle has no "(.*)"$"#)]
+fn assert_bundle_missing(world: &mut LockWorld, key: String) {
+    assert_eq!(value_at(&exported_bundle(world).settings, &key), None);
+}
+
+#[when("another machine with its own password takes over the settings")]
+fn switch_machine(world: &mut LockWorld) {
+    world.settings = Settings::default();
+    world.settings.set_password("other machine");
+}
+
+#[when("the bundle is imported")]
+fn import_bundle(world: &mut LockWorld) {
+    let plan = plan_import(&world.settings, exported_bundle(world));
+    if let Ok(plan) = &plan {
+        world.resolved = Some(Ok(plan.settings.clone()));
+    }
+    world.import = Some(plan);
+}
+
+#[then(regex = r#"^the import changes "(.*)" from (.*) to (.*)$"#)]
+fn assert_import_change(world: &mut LockWorld, key: String, before: String, after: String) {
+    let change = import_plan(world)
+        .changes
+        .iter()
+        .find(|change| change.key == key)
+        .unwrap_or_else(|| panic!("{key} is not changed"));
+    let shown = |text: &str| (text != "unset").then(|| shown_value(text));
+    assert_eq!(change.before, shown(&before));
+    assert_eq!(change.after, shown(&after));
+}
+
+#[then(regex = r#"^the import does not change "(.*)"$"#)]
+fn assert_import_unchanged(world: &mut LockWorld, key: String) {
+    assert!(
+        import_plan(world)
+            .changes
+            .iter()
+            .all(|change| change.key != key)
+    );
+}
+
+#[then("the import changes nothing")]
+fn assert_import_empty(world: &mut LockWorld) {
+    assert!(import_plan(world).is_empty());
+}
+
+#[then(regex = r#"^the import is refused because (.*)$"#)]
+fn assert_import_refused(world: &mut LockWorld, reason: String) {
+    match world.import.as_ref().expect("imported") {
+        Err(err) => assert!(
+            err.to_string().contains(&reason),
+            "{err} does not mention {reason:?}"
+        ),
+        Ok(plan) => panic!("expected the import to fail, got {:?}", plan.changes),
+    }
+}
+
+#[then(regex = r"^a bundle of format version (\d+) is refused$")]
+fn assert_bundle_version_refused(_world: &mut LockWorld, version: u32) {
+    let text = format!(r#"{{"bundle_version": {version}, "exported_at": "", "settings": {{}}}}"#);
+    assert!(matches!(
+        parse_bundle(text.as_bytes()),
+        Err(BundleError::UnsupportedVersion { .. })
+    ));
+}
+
+#[then(regex = r#"^the profile "(.*)" still has its own authenticators$"#)]
+fn assert_profile_authenticators(world: &mut LockWorld, name: String) {
+    let profile = &resolved_settings(world).profiles[&name];
+    assert!(profile.authenticators.is_some());
+}
+
 #[tokio::test]
 async fn cucumber_features() {
     LockWorld::run("tests/features").await;
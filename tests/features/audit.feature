@@ -0,0 +1,24 @@
+Feature: Audit trail
+
+  Scenario: Events are appended and summarized
+    Given an audit log in a temporary directory rotating at 4096 bytes and keeping 2 files
+    When 1 lock session with 2 failed attempts lasting 42 seconds is audited
+    Then the audit summary shows 1 lock, 1 unlock, 2 failed attempts and 42 locked seconds
+    And every failed attempt records only the input length
+
+  Scenario: Failed settings saves are counted
+    Given an audit log in a temporary directory rotating at 4096 bytes and keeping 2 files
+    When a settings save failure is audited
+    Then the audit summary counts 1 failed settings save
+
+  Scenario: Old log files are rotated away
+    Given an audit log in a temporary directory rotating at 200 bytes and keeping 2 files
+    When 40 lock sessions with 0 failed attempts lasting 1 seconds are audited
+    Then at most 3 audit files exist
+    And fewer than 160 audit records remain
+
+  Scenario: Settings changes name the fields that changed
+    Given default settings
+    When a settings snapshot is taken
+    And the user disables blur
+    Then the changed settings are "blur_enabled"
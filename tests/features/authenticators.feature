@@ -0,0 +1,30 @@
+Feature: Unlock factors
+
+  Scenario: The default chain asks only for the password
+    Given default settings
+    When the user sets the password "hunter2"
+    And the unlock chain is built
+    Then the unlock prompt is "Password"
+    When "hunter2" is submitted
+    Then the unlock is accepted
+
+  Scenario: A PIN can follow the password
+    Given default settings
+    When the user sets the password "hunter2"
+    And the user adds the PIN "4711"
+    And the unlock chain is built
+    When "hunter2" is submitted
+    Then more input is needed
+    And the unlock prompt is "PIN"
+    When "4711" is submitted
+    Then the unlock is accepted
+
+  Scenario: A wrong second factor restarts the chain
+    Given default settings
+    When the user sets the password "hunter2"
+    And the user adds the PIN "4711"
+    And the unlock chain is built
+    When "hunter2" is submitted
+    And "0000" is submitted
+    Then the unlock is rejected
+    And the unlock prompt is "Password"
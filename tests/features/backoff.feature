@@ -0,0 +1,43 @@
+Feature: Failed attempt backoff
+
+  Background:
+    Given a backoff policy with 3 free attempts, a 2 second base delay capped at 60 seconds and a 300 second lockout after 10 failures
+
+  Scenario: The first failures cost nothing
+    When 3 attempts fail
+    Then attempts are allowed
+
+  Scenario: Delays double after the free attempts
+    When 4 attempts fail
+    Then attempts are blocked for 2 seconds
+    When 1 attempts fail
+    Then attempts are blocked for 4 seconds
+    When 3 seconds pass
+    Then attempts are blocked for 1 seconds
+    When 1 seconds pass
+    Then attempts are allowed
+
+  Scenario: Delays are capped
+    When 9 attempts fail
+    Then attempts are blocked for 60 seconds
+
+  Scenario: Reaching the limit imposes the hard cooldown
+    When 10 attempts fail
+    Then attempts are blocked for 300 seconds
+
+  Scenario: A successful unlock clears the counter
+    When 6 attempts fail
+    And an attempt succeeds
+    Then attempts are allowed
+    And 0 failures are recorded
+
+  Scenario: Absurd delays are capped at a day
+    Given a backoff policy with 0 free attempts, a 18446744073709551615 second base delay capped at 18446744073709551615 seconds and a 18446744073709551615 second lockout after 2 failures
+    When 1 attempts fail
+    Then attempts are blocked for 86400 seconds
+    When 1 attempts fail
+    Then attempts are blocked for 86400 seconds
+
+  Scenario: Loaded settings cap the delays
+    When settings with the backoff {"max_delay_secs": 18446744073709551615, "lockout_secs": 99999999} are parsed
+    Then the loaded backoff delays are at most 86400 seconds
@@ -0,0 +1,68 @@
+Feature: Settings bundles
+
+  Background:
+    Given default settings
+    And the user's settings set "blur_radius" to 30
+    And the user's settings set "monitor_mode" to "all"
+    And the profile "overnight" sets "lock_message" to "Back tomorrow"
+    And the profile "overnight" sets "authenticators" to [{"kind": "pin", "hash": {"algorithm": "argon2id", "memory_kib": 8, "iterations": 1, "parallelism": 1, "salt": "00", "hash": "00"}}]
+    When the user sets the password "correct horse"
+
+  Scenario: Exports leave secrets and machine state behind
+    When the settings are exported
+    Then the bundle has "blur_radius" set to 30
+    And the bundle has "monitor_mode" set to "all"
+    And the bundle has "profiles.overnight.lock_message" set to "Back tomorrow"
+    And the bundle has no "password_hash"
+    And the bundle has no "password_changed_at"
+    And the bundle has no "profiles.overnight.authenticators"
+
+  Scenario: Importing on another machine keeps its password
+    When the settings are exported
+    And the bundle is written to a file and read back
+    And another machine with its own password takes over the settings
+    And the bundle is imported
+    Then the import changes "blur_radius" from 12 to 30
+    And the import changes "monitor_mode" from "custom" to "all"
+    And the import does not change "password_hash"
+    And the setting "profiles.overnight.lock_message" has the value "Back tomorrow"
+    And the password "other machine" is still set
+
+  Scenario: Importing a bundle twice changes nothing the second time
+    When the settings are exported
+    And the bundle is imported
+    Then the import changes nothing
+
+  Scenario: A redefined profile keeps the local authenticators
+    Given a bundle with the settings {"schema_version": 1, "profiles": {"overnight": {"blur_radius": 40}}}
+    When the bundle is imported
+    Then the setting "profiles.overnight.blur_radius" has the value 40
+    And the profile "overnight" still has its own authenticators
+
+  Scenario: Older bundles are migrated
+    Given a bundle with the settings {"show_settings_on_startup": true}
+    When the bundle is imported
+    Then the import changes "open_settings_on_startup" from false to true
+
+  Scenario Outline: Bundles that do not fit the schema are refused
+    Given a bundle with the settings <settings>
+    When the bundle is imported
+    Then the import is refused because <reason>
+
+    Examples:
+      | settings                                                           | reason                      |
+      | {"schema_version": 1, "monitor_mode": "specific"}                  | must be one of              |
+      | {"schema_version": 1, "blur_raduis": 4}                            | is not a setting            |
+      | {"schema_version": 1, "blur_radius": 100}                          | must be between 1 and 64    |
+      | {"schema_version": 1, "password_hash": null}                       | cannot be imported          |
+      | {"schema_version": 1, "password": "hunter2"}                       | cannot be imported          |
+      | {"schema_version": 1, "profiles": {"x": {"authenticators": []}}}   | cannot be imported          |
+      | {"schema_version": 99}                                             | schema version 99           |
+      | {"schema_version": 1, "profiles": {"x": {"blur_radius": 100}}}     | must be between 1 and 64    |
+      | {"schema_version": 1, "profiles": {"x": {"blur_raduis": 4}}}       | is not a setting            |
+      | {"schema_version": 1, "profiles": {"x": {"monitor_mode": "some"}}} | must be one of              |
+      | {"schema_version": 1, "backoff": {"max_delay_secs": 999999}}       | must be between 0 and 86400 |
+      | {"schema_version": 1, "disable_monitors": [2]}                     | must be of type             |
+
+  Scenario: Bundles from newer releases are refused
+    Then a bundle of format version 2 is refused
@@ -0,0 +1,27 @@
+Feature: Helpdesk challenge-response
+
+  Background:
+    Given a challenge secret shown after 3 failures and valid for 600 seconds
+
+  Scenario: The helpdesk response unlocks
+    When a challenge is issued
+    Then the helpdesk response looks like "dddd-dddd"
+    And the helpdesk response is accepted
+    And the helpdesk response without the dash is accepted
+
+  Scenario: Wrong responses are refused
+    When a challenge is issued
+    Then the response "0000-0000" is refused
+    And the response for another challenge is refused
+    And a response computed with another secret is refused
+
+  Scenario: A challenge appears only after enough failures
+    Then a challenge is not due after 2 failures
+    And a challenge is due after 3 failures
+
+  Scenario: A challenge stays until it expires
+    When a challenge is issued
+    Then a challenge is not due after 4 failures
+    When 600 seconds pass
+    Then the challenge has expired
+    And a challenge is due after 4 failures
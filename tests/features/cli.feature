@@ -0,0 +1,149 @@
+Feature: Command line
+
+  Scenario: Without arguments dwmlock locks after asking
+    When the arguments "" are parsed
+    Then the command locks with confirmation
+    And the command does not open the settings dialog first
+
+  Scenario: Lock without confirmation
+    When the arguments "lock --yes" are parsed
+    Then the command locks without confirmation
+
+  Scenario: The old settings flags still open the dialog
+    When the arguments "--settings-on-startup" are parsed
+    Then the command locks with confirmation
+    And the command opens the settings dialog first
+
+  Scenario: The old setup flags are still accepted
+    When the arguments "--add-guest-password plumber 2.5" are parsed
+    Then the command adds a guest password "plumber" for 2.5 hours
+
+  Scenario Outline: Unlock tokens are valid for at most a year
+    When the arguments "token sign key.hex desk-1 <hours>" are parsed
+    Then the arguments are rejected with the token help
+
+    Examples:
+      | hours                |
+      | 8785                 |
+      | 18446744073709551615 |
+      | -1                   |
+
+  Scenario Outline: Guest password lifetimes must be finite and at most a year
+    When the arguments "--add-guest-password plumber <hours>" are parsed
+    Then the arguments are rejected with the lock help
+
+    Examples:
+      | hours |
+      | 0     |
+      | inf   |
+      | NaN   |
+      | 1e10  |
+      | 8785  |
+
+  Scenario: Settings subcommands
+    When the arguments "settings get password_policy.min_length" are parsed
+    Then the command reads the setting "password_policy.min_length"
+    When the arguments "settings set blur_radius 4" are parsed
+    Then the command stores 4 at "blur_radius"
+    When the arguments "settings set lock_message Lunch" are parsed
+    Then the command stores "Lunch" at "lock_message"
+    When the arguments "settings reset blur_radius" are parsed
+    Then the command resets "blur_radius"
+    When the arguments "settings list" are parsed
+    Then the command lists the settings
+
+  Scenario: Other subcommands
+    When the arguments "config check" are parsed
+    Then the command checks the settings file
+    When the arguments "--check-config" are parsed
+    Then the command checks the settings file
+    When the arguments "version" are parsed
+    Then the command prints the version
+    When the arguments "hash-password" are parsed
+    Then the command hashes a password read from standard input
+
+  Scenario: Global options may appear anywhere
+    When the arguments "settings --profile overnight list --set blur_radius=4" are parsed
+    Then the command lists the settings
+    And the profile "overnight" is requested
+    And the override "blur_radius" is 4
+
+  Scenario: Help for each command
+    When the arguments "help" are parsed
+    Then the command prints the main help
+    When the arguments "settings --help" are parsed
+    Then the command prints the settings help
+    When the arguments "help token" are parsed
+    Then the command prints the token help
+
+  Scenario: Help flags only count where a flag is expected
+    When the arguments "settings set lock_message -h" are parsed
+    Then the command stores "-h" at "lock_message"
+    When the arguments "lock --yes --help" are parsed
+    Then the command prints the lock help
+    When the arguments "--generate-shares 2 -h" are parsed
+    Then the arguments are rejected with the lock help
+
+  Scenario: Setup options take nothing beside their own arguments
+    When the arguments "--generate-shares 2 3" are parsed
+    Then the command generates 3 shares with a threshold of 2
+    When the arguments "--generate-shares 2 3 4" are parsed
+    Then the arguments are rejected with the lock help
+    When the arguments "--enroll-totp now" are parsed
+    Then the arguments are rejected with the lock help
+    When the arguments "--yes --generate-recovery-codes" are parsed
+    Then the arguments are rejected with the lock help
+
+  Scenario: Mistakes are usage errors with the relevant help
+    When the arguments "config frobnicate" are parsed
+    Then the arguments are rejected with the config help
+    When the arguments "settings set blur_radius" are parsed
+    Then the arguments are rejected with the settings help
+    When the arguments "lock --frobnicate" are parsed
+    Then the arguments are rejected with the lock help
+    When the arguments "unlock" are parsed
+    Then the arguments are rejected with the main help
+    When the arguments "--profile" are parsed
+    Then the arguments are rejected with the main help
+    And usage errors exit with status 2
+
+  Scenario: Reading settings hides secrets
+    Given default settings
+    When the user sets the password "correct horse"
+    And the setting "blur_radius" is read
+    Then the value read is 12
+    When the setting "password_hash" is read
+    Then the value read is hidden
+    When the setting "password_hash.salt" is read
+    Then the value read is hidden
+    When the setting "no_such_setting" is read
+    Then nothing is read
+
+  Scenario: Listing settings hides secrets
+    Given default settings
+    When the user sets the password "correct horse"
+    And the settings are listed
+    Then the listing shows "blur_radius" as 12
+    And the listing shows "password_hash" as hidden
+    And the listing contains no secret
+
+  Scenario: Setting a value checks its type and name
+    Given default settings
+    When the setting "blur_radius" is set to 4
+    Then the setting "blur_radius" has the value 4
+    When the setting "blur_radius" is set to "wide"
+    Then resolving fails because of the command line
+    When the setting "blur_raduis" is set to 4
+    Then resolving fails because of the command line
+
+  Scenario: Resetting restores defaults but keeps the password
+    Given default settings
+    And the user's settings set "blur_radius" to 30
+    And the user's settings set "blur_enabled" to false
+    When the user sets the password "correct horse"
+    And the setting "blur_radius" is reset
+    Then the setting "blur_radius" has the value 12
+    And the setting "blur_enabled" has the value false
+    When every setting is reset
+    Then the setting "blur_enabled" has the value true
+    And the password "correct horse" is still set
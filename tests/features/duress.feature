@@ -0,0 +1,29 @@
+Feature: Duress password
+
+  Background:
+    Given default settings
+    When the user sets the password "hunter2"
+    And the user sets the duress password "help me"
+    And the unlock chain is built
+
+  Scenario: The duress password unlocks and is flagged
+    When "help me" is submitted
+    Then the unlock is accepted
+    And the unlock was under duress
+
+  Scenario: The real password is not flagged
+    When "hunter2" is submitted
+    Then the unlock is accepted
+    And the unlock was not under duress
+
+  Scenario: Duress actions leave a marker file and a flagged audit entry
+    Given an audit log in a temporary directory rotating at 4096 bytes and keeping 2 files
+    When the duress actions write a marker file and an audit entry
+    Then the duress marker file exists
+    And the audit log records 1 duress unlock
+
+  Scenario: A failing duress action is only recorded in the audit log
+    Given an audit log in a temporary directory rotating at 4096 bytes and keeping 2 files
+    When the duress actions run a program that does not exist and write an audit entry
+    Then the audit log records 1 duress unlock
+    And the audit log records 1 failed duress action
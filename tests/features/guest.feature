@@ -0,0 +1,37 @@
+Feature: Guest passwords
+
+  Scenario: A guest password unlocks inside its window
+    Given default settings
+    And a guest password "cover-shift" valid from -1 hours to 8 hours from now
+    When the guest password "cover-shift" is tried
+    Then the guest unlock is accepted
+    And the guest session expires in 8 hours
+
+  Scenario: A guest password is refused before its window opens
+    Given default settings
+    And a guest password "cover-shift" valid from 1 hours to 8 hours from now
+    When the guest password "cover-shift" is tried
+    Then the guest unlock is rejected
+
+  Scenario: A guest password is refused after it expires
+    Given default settings
+    And a guest password "cover-shift" valid from -8 hours to -1 hours from now
+    When the guest password "cover-shift" is tried
+    Then the guest unlock is rejected
+
+  Scenario: A guest password with a use limit runs out
+    Given default settings
+    And a guest password "one-time" with 1 use valid for 8 hours
+    When the guest password "one-time" is tried
+    Then the guest unlock is accepted
+    When the guest password "one-time" is tried
+    Then the guest unlock is rejected
+
+  Scenario: Expired guest access is pruned
+    Given default settings
+    And a guest password "old" valid from -8 hours to -1 hours from now
+    And a guest password "current" valid from -1 hours to 8 hours from now
+    And a guest session that expired 1 hours ago
+    When guest access is pruned
+    Then 1 guest password remains
+    And there is no guest session
@@ -0,0 +1,22 @@
+Feature: Unicode password entry
+
+  Scenario: Surrogate pairs arriving in two messages form one character
+    When the code points "U+1F600" are typed
+    Then the typed input has 1 characters
+
+  Scenario: Backspace removes a whole grapheme cluster
+    When the code points "U+0061 U+0065 U+0301" are typed
+    And backspace is pressed
+    Then the typed input has 1 characters
+
+  Scenario: Decomposed input matches a composed password
+    Given default settings
+    When the user sets the password "café"
+    And the code points "U+0063 U+0061 U+0066 U+0065 U+0301" are typed
+    Then the typed input unlocks
+
+  Scenario: Umlauts and Cyrillic can be typed
+    Given default settings
+    When the user sets the password "Grüße Привет"
+    And the text "Grüße Привет" is typed
+    Then the typed input unlocks
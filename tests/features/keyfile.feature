@@ -0,0 +1,47 @@
+Feature: Keyfile unlock
+
+  Background:
+    Given an empty keyfile directory
+
+  Scenario: A keyfile at the exact configured path matches
+    Given a keyfile for contents "usb stick secret" is configured at "stick/dwmlock.key"
+    When the file "stick/dwmlock.key" containing "usb stick secret" is created
+    And the keyfile locations are polled
+    Then the keyfile at "stick/dwmlock.key" matches
+
+  Scenario: Wildcards find the keyfile wherever it is mounted
+    Given a keyfile for contents "usb stick secret" is configured at "stick-*/keys/*.KEY"
+    When the file "stick-b/keys/dwmlock.key" containing "usb stick secret" is created
+    And the keyfile locations are polled
+    Then the keyfile at "stick-b/keys/dwmlock.key" matches
+
+  Scenario: A file with other contents does not match
+    Given a keyfile for contents "usb stick secret" is configured at "stick/*.key"
+    When the file "stick/dwmlock.key" containing "a different secret" is created
+    And the keyfile locations are polled
+    Then no keyfile matches
+
+  Scenario: A found keyfile is checked again before unlocking
+    Given a keyfile for contents "usb stick secret" is configured at "stick/dwmlock.key"
+    When the file "stick/dwmlock.key" containing "usb stick secret" is created
+    And the keyfile locations are polled
+    Then the found keyfile still matches
+    When the file "stick/dwmlock.key" containing "a different secret" is created
+    Then the found keyfile no longer matches
+
+  Scenario: Nothing matches while the stick is absent
+    Given a keyfile for contents "usb stick secret" is configured at "stick/dwmlock.key"
+    When the keyfile locations are polled
+    Then no keyfile matches
+
+  Scenario Outline: Wildcard patterns
+    Then the pattern "<pattern>" matches "<name>": <matches>
+
+    Examples:
+      | pattern   | name        | matches |
+      | *.key     | dwmlock.key | yes     |
+      | *.key     | dwmlock.txt | no      |
+      | DWM?OCK.* | dwmlock.key | yes     |
+      | stick-*-b | stick-a-b   | yes     |
+      | stick-*-b | stick-a-c   | no      |
+      | ?         |             | no      |
@@ -0,0 +1,78 @@
+Feature: Layered configuration
+
+  Background:
+    Given default settings
+    And the user's settings set "blur_radius" to 20
+
+  Scenario: Values the user has not changed come from the defaults
+    When the settings layers are resolved
+    Then the setting "blur_radius" has the value 20
+    And the setting "blur_radius" comes from the user's settings file
+    And the setting "monitor_mode" comes from the built-in defaults
+
+  Scenario: The environment overrides the user's file
+    Given the environment sets "DWMLOCK_BLUR_RADIUS" to "25"
+    And the environment sets "PATH" to "C:\Windows"
+    When the settings layers are resolved
+    Then the setting "blur_radius" has the value 25
+    And the setting "blur_radius" comes from the environment
+    And the setting "blur_radius" is not locked
+
+  Scenario: A double underscore reaches into nested settings
+    Given the environment sets "DWMLOCK_PASSWORD_POLICY__MIN_LENGTH" to "12"
+    When the settings layers are resolved
+    Then the setting "password_policy.min_length" has the value 12
+    And the setting "password_policy.min_length" comes from the environment
+    And the setting "password_policy.deny_list" comes from the built-in defaults
+
+  Scenario: Command-line flags override the environment
+    Given the environment sets "DWMLOCK_MONITOR_MODE" to "none"
+    And the command line is "--set monitor_mode=all --set blur_enabled=false"
+    When the settings layers are resolved
+    Then the setting "monitor_mode" has the value "all"
+    And the setting "monitor_mode" comes from the command line
+    And the setting "blur_enabled" has the value false
+
+  Scenario: The machine policy wins and locks its keys
+    Given the environment sets "DWMLOCK_BLUR_RADIUS" to "25"
+    And the machine policy sets "blur_radius" to 32
+    And the machine policy sets "show_settings_button" to false
+    And the command line is "--set blur_radius=4"
+    When the settings layers are resolved
+    Then the setting "blur_radius" has the value 32
+    And the setting "blur_radius" comes from the machine policy
+    And the setting "blur_radius" is locked
+    And the setting "show_settings_button" has the value false
+    And the setting "monitor_mode" is not locked
+
+  Scenario: A nested policy key locks the object around it
+    Given the machine policy sets "password_policy.min_length" to 14
+    When the settings layers are resolved
+    Then the setting "password_policy.min_length" has the value 14
+    And the setting "password_policy" is locked
+    And the setting "password_policy.deny_list" is not locked
+
+  Scenario: Saving leaves other layers out of the user's file
+    Given the machine policy sets "blur_radius" to 32
+    And the environment sets "DWMLOCK_MONITOR_MODE" to "all"
+    When the settings layers are resolved
+    And the resolved settings are saved to a settings file
+    Then the saved settings have "blur_radius" set to 20
+    And the saved settings have "monitor_mode" set to "custom"
+
+  Scenario: An override edited after resolving is saved
+    Given the environment sets "DWMLOCK_MONITOR_MODE" to "all"
+    When the settings layers are resolved
+    And the resolved monitor mode is changed to "none"
+    And the resolved settings are saved to a settings file
+    Then the saved settings have "monitor_mode" set to "none"
+
+  Scenario: A bad value is blamed on the layer that set it
+    Given the environment sets "DWMLOCK_BLUR_RADIUS" to "wide"
+    When the settings layers are resolved
+    Then resolving fails because of the environment
+
+  Scenario: A set flag without a value is rejected
+    Given the command line is "--set blur_radius"
+    When the settings layers are resolved
+    Then resolving fails because of the command line
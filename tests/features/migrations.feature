@@ -0,0 +1,24 @@
+Feature: Settings schema migrations
+
+  Scenario: A pre-versioning file is upgraded to the current schema
+    When the settings fixture "v0-baseline.json" is migrated
+    Then it matches the fixture "v0-baseline.migrated.json"
+    And the fixture was upgraded
+
+  Scenario: The newer option name wins when both are present
+    When the settings fixture "v0-both-names.json" is migrated
+    Then it matches the fixture "v0-both-names.migrated.json"
+
+  Scenario: An upgraded file loads with the renamed option
+    When the settings fixture "v0-baseline.json" is parsed
+    Then the parsed settings open on startup
+    And the parsed settings are at the current schema version
+
+  Scenario: A current file is left as it is
+    When the settings fixture "v1-current.json" is migrated
+    Then it matches the fixture "v1-current.json"
+    And the fixture was not upgraded
+
+  Scenario: A file from a newer version is refused
+    When the settings fixture "v99-future.json" is parsed
+    Then the settings are refused as written by schema version 99
@@ -0,0 +1,19 @@
+Feature: Password storage
+
+  Scenario: New passwords are stored as salted hashes
+    Given default settings
+    When the user sets the password "hunter2"
+    Then the saved settings do not contain "hunter2"
+    And the password "hunter2" unlocks
+    And the password "media" does not unlock
+
+  Scenario: Plaintext passwords migrate on load
+    Given a legacy settings file with password "letmein"
+    When the legacy password is migrated
+    Then the saved settings do not contain "letmein"
+    And the password "letmein" unlocks
+
+  Scenario: Missing passwords fall back to the default
+    Given default settings
+    When the legacy password is migrated
+    Then the password "media" unlocks
@@ -0,0 +1,66 @@
+Feature: Password policy
+
+  Scenario Outline: The default policy
+    Given default settings
+    Then the password "<password>" is <verdict>
+
+    Examples:
+      | password          | verdict                  |
+      | correct horse     | allowed                  |
+      | short             | refused as TooShort      |
+      | media             | refused as TooShort      |
+      | PASSWORD          | refused as DenyListed    |
+      | Dwmlock           | refused as TooShort      |
+      | 12345678          | refused as DenyListed    |
+      | héllo wörld       | allowed                  |
+
+  Scenario: Character classes can be required
+    Given default settings
+    And the policy requires 3 character classes
+    Then the password "alllowercase" is refused as TooFewCharacterClasses
+    And the password "Mixed-case1" is allowed
+
+  Scenario: The deny-list includes the default password at any length
+    Given default settings
+    And the policy requires at least 1 characters
+    Then the password "Media" is refused as DenyListed
+
+  Scenario: A refused change keeps the current password
+    Given default settings
+    When the user sets the password "hunter2hunter2"
+    And the user tries to change the password to "short"
+    Then the password change is refused as TooShort
+    When the unlock chain is built
+    And "hunter2hunter2" is submitted
+    Then the unlock is accepted
+
+  Scenario: Locking with the default password is refused unless allowed
+    Given default settings
+    Then locking is refused as DefaultPassword
+    When the policy allows the default password
+    Then locking is allowed
+
+  Scenario: Locking is allowed once a real password is set
+    Given default settings
+    When the user tries to change the password to "correct horse"
+    Then the password change is accepted
+    And locking is allowed
+
+  Scenario: An old password has to be changed before locking
+    Given default settings
+    And the policy limits password age to 90 days
+    When the user sets the password "correct horse"
+    And the password was changed 100 days ago
+    Then locking is refused as Expired
+
+  Scenario Outline: Expiry dates beyond the calendar never expire
+    Given default settings
+    And the policy limits password age to <days> days
+    When the user sets the password "correct horse"
+    And the password change time is recorded as "<changed>"
+    Then locking is allowed
+
+    Examples:
+      | days       | changed                   |
+      | 4294967295 | 2026-01-01T00:00:00+00:00 |
+      | 100000000  | 9999-12-31T00:00:00+00:00 |
@@ -0,0 +1,54 @@
+Feature: Settings profiles
+
+  Background:
+    Given default settings
+    And the profile "presentation" sets "monitor_mode" to "none"
+    And the profile "presentation" sets "dismiss_notifications_on_startup" to true
+    And the profile "overnight" sets "blur_radius" to 40
+    And the profile "overnight" sets "lock_message" to "Back tomorrow"
+
+  Scenario: Without a profile the base settings apply
+    When no profile is resolved
+    Then the setting "blur_radius" has the value 12
+    And the setting "monitor_mode" comes from the built-in defaults
+    And no profile is active
+
+  Scenario: A requested profile overrides the base settings
+    When the profile "overnight" is resolved
+    Then the setting "blur_radius" has the value 40
+    And the setting "lock_message" has the value "Back tomorrow"
+    And the setting "blur_radius" comes from the selected profile
+    And the setting "monitor_mode" comes from the built-in defaults
+    And the active profile is "overnight"
+
+  Scenario: The default profile applies when none is requested
+    Given the default profile is "presentation"
+    When no profile is resolved
+    Then the setting "monitor_mode" has the value "none"
+    And the active profile is "presentation"
+
+  Scenario: A requested profile beats the default profile
+    Given the default profile is "presentation"
+    When the profile "overnight" is resolved
+    Then the setting "monitor_mode" has the value "custom"
+    And the setting "blur_radius" has the value 40
+
+  Scenario: An unknown profile is reported
+    When the profile "lunch" is resolved
+    Then resolving fails because of the selected profile
+
+  Scenario: The environment overrides the profile
+    Given the command line is "--profile overnight"
+    And the environment sets "DWMLOCK_BLUR_RADIUS" to "10"
+    When the settings layers are resolved
+    Then the setting "blur_radius" has the value 10
+    And the setting "blur_radius" comes from the environment
+    And the setting "lock_message" has the value "Back tomorrow"
+
+  Scenario: Saving keeps profile values out of the base settings
+    Given the command line is "--profile overnight"
+    When the settings layers are resolved
+    And the resolved settings are saved to a settings file
+    Then the saved settings have "blur_radius" set to 12
+    And the saved settings have no "lock_message"
+    And the saved settings have "profiles.overnight.blur_radius" set to 40
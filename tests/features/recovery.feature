@@ -0,0 +1,26 @@
+Feature: Recovery codes
+
+  Scenario: Each recovery code unlocks exactly once
+    Given default settings
+    When recovery codes are generated
+    Then 10 recovery codes remain
+    And the saved settings do not contain the first recovery code
+    When the first recovery code is entered
+    Then the recovery code is accepted
+    And 9 recovery codes remain
+    When the first recovery code is entered
+    Then the recovery code is rejected
+
+  Scenario: Codes may be typed in lowercase without dashes
+    Given default settings
+    When recovery codes are generated
+    And the first recovery code is entered in lowercase without dashes
+    Then the recovery code is accepted
+
+  Scenario: Regenerating invalidates the previous set
+    Given default settings
+    When recovery codes are generated
+    And recovery codes are generated
+    And a code from the previous set is entered
+    Then the recovery code is rejected
+    And 10 recovery codes remain
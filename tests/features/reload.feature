@@ -0,0 +1,34 @@
+Feature: Reloading settings while locked
+
+  Scenario: Display settings are applied at once
+    Given default settings
+    When the settings on disk set "blur_radius" to 30
+    And the settings on disk set "monitor_mode" to "all"
+    And the settings reload is planned
+    Then the reload applies "blur_radius, monitor_mode"
+    And the reload defers nothing
+    And the reloaded settings have "blur_radius" set to 30
+
+  Scenario: A new password waits for the next lock
+    Given default settings
+    When the settings on disk set "blur_radius" to 30
+    And the password on disk is changed to "Correct-Horse-9"
+    And the settings reload is planned
+    Then the reload applies "blur_radius"
+    And the reload defers "password_changed_at, password_hash"
+    And the reloaded settings have "blur_radius" set to 30
+    And the reloaded settings still accept the default password
+
+  Scenario: Unlock alternatives are not picked up while locked
+    Given default settings
+    When the settings on disk set "show_settings_button" to false
+    And a challenge secret is configured on disk
+    And the settings reload is planned
+    Then the reload applies "show_settings_button"
+    And the reload defers "challenge"
+    And the reloaded settings have no challenge configured
+
+  Scenario: An unchanged file changes nothing
+    Given default settings
+    When the settings reload is planned
+    Then the reload is empty
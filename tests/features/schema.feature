@@ -0,0 +1,16 @@
+Feature: Settings schema
+
+  Scenario: The published schema matches the settings types
+    Then the published settings schema is up to date
+
+  Scenario: Monitor modes are spelled out
+    Then the schema allows "monitor_mode" to be "none", "all" or "custom"
+
+  Scenario: The blur range of the settings dialog is published
+    Then the schema limits "blur_radius" to 1 through 64
+
+  Scenario: Published defaults are the built-in defaults
+    Then every default in the schema matches the built-in settings
+
+  Scenario: Every setting is described
+    Then every setting in the schema has a description
@@ -0,0 +1,14 @@
+Feature: Secrets in memory
+
+  Scenario: Debug output redacts a legacy password
+    Given a legacy settings file with password "letmein"
+    Then the debug output does not contain "letmein"
+
+  Scenario: Debug output redacts typed input
+    When the text "hunter2" is typed
+    Then the debug output does not contain "hunter2"
+
+  Scenario: Clearing the input wipes it
+    When the text "hunter2" is typed
+    And the typed input is cleared
+    Then the typed input has 0 characters
@@ -0,0 +1,69 @@
+Feature: Loading the settings file
+
+  Scenario: A syntax error is reported with its location and the file is kept
+    Given a settings file copied from the fixture "broken-trailing-comma.json"
+    When the settings file is loaded
+    Then loading fails at line 5 column 1
+    And the settings file is unchanged
+    And a timestamped backup of the settings file exists
+
+  Scenario: A value of the wrong type is reported with its location
+    Given a settings file copied from the fixture "wrong-type.json"
+    When the settings file is loaded
+    Then loading fails at line 4 column 23
+    And the settings file is unchanged
+
+  Scenario: Checking a broken file changes nothing
+    Given a settings file copied from the fixture "broken-trailing-comma.json"
+    When the settings file is checked
+    Then the check fails at line 5 column 1
+    And the settings file is unchanged
+    And no backup of the settings file exists
+
+  Scenario: Checking a valid file passes
+    Given a settings file copied from the fixture "v1-current.json"
+    When the settings file is checked
+    Then the check passes
+
+  Scenario: A missing file is created with defaults
+    Given no settings file
+    When the settings file is loaded
+    Then the settings file exists
+
+  Scenario: Saving keeps the previous file as a rolling backup
+    Given a settings file copied from the fixture "v1-current.json"
+    When settings with a blur radius of 12 are saved to the settings file
+    Then the settings file has a blur radius of 12
+    And the previous settings file is kept as a backup
+    And no temporary settings file is left behind
+
+  Scenario: A backup that cannot be refreshed does not fail the save
+    Given a settings file copied from the fixture "v1-current.json"
+    And a directory is in the way of the settings backup
+    When settings with a blur radius of 12 are saved to the settings file
+    Then the settings file has a blur radius of 12
+    And no temporary settings file is left behind
+
+  Scenario: A file from a newer version is not overwritten
+    Given a settings file copied from the fixture "v99-future.json"
+    When settings with a blur radius of 12 are saved to the settings file
+    Then saving is refused as written by schema version 99
+    And the settings file is unchanged
+
+  Scenario: Reading a broken file for a reload writes nothing
+    Given a settings file copied from the fixture "broken-trailing-comma.json"
+    When the settings file is read without writing
+    Then loading fails at line 5 column 1
+    And the settings file is unchanged
+    And no backup of the settings file exists
+
+  Scenario: Reading an old file for a reload does not upgrade it
+    Given a settings file copied from the fixture "v0-baseline.json"
+    When the settings file is read without writing
+    Then the settings file is unchanged
+    And no backup of the settings file exists
+
+  Scenario: Reading a missing file for a reload does not create it
+    Given no settings file
+    When the settings file is read without writing
+    Then the settings file does not exist
@@ -0,0 +1,55 @@
+Feature: Shamir share unlock
+
+  Scenario Outline: Any threshold-sized subset rebuilds the secret
+    Given the secret "kiosk master key" is split into <count> shares with threshold <threshold>
+    When shares <picked> are combined
+    Then the combined secret is "kiosk master key"
+
+    Examples:
+      | threshold | count | picked    |
+      | 2         | 3     | 1,2       |
+      | 2         | 3     | 3,1       |
+      | 3         | 5     | 5,2,4     |
+      | 3         | 5     | 1,2,3,4,5 |
+
+  Scenario: Fewer shares than the threshold do not reveal the secret
+    Given the secret "kiosk master key" is split into 5 shares with threshold 3
+    When shares 1,4 are combined
+    Then the combined secret is not "kiosk master key"
+
+  Scenario: Shares survive their text form
+    Given the secret "kiosk master key" is split into 3 shares with threshold 2
+    When shares 2,3 are encoded, parsed and combined
+    Then the combined secret is "kiosk master key"
+
+  Scenario: A threshold above the share count is refused
+    Then splitting into 2 shares with threshold 3 fails
+
+  Scenario: Two of three shares unlock, one after another
+    Given default settings
+    When 2 of 3 unlock shares are enrolled as the only factor
+    And the unlock chain is built
+    Then the unlock prompt is "Share 1 of 2"
+    When unlock share 1 is submitted
+    Then more input is needed
+    And the unlock prompt is "Share 1 of 2 accepted, next share"
+    When unlock share 3 is submitted
+    Then the unlock is accepted
+
+  Scenario: Repeating a share restarts the collection
+    Given default settings
+    When 2 of 3 unlock shares are enrolled as the only factor
+    And the unlock chain is built
+    And unlock share 2 is submitted
+    And unlock share 2 is submitted
+    Then the unlock is rejected
+    And the unlock prompt is "Share 1 of 2"
+
+  Scenario: Shares from another enrollment do not unlock
+    Given default settings
+    When 2 of 3 unlock shares are enrolled as the only factor
+    And 2 of 3 unlock shares are enrolled as the only factor
+    And the unlock chain is built
+    And a share from the previous enrollment is submitted
+    And unlock share 1 is submitted
+    Then the unlock is rejected
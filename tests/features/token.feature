@@ -0,0 +1,46 @@
+Feature: Signed unlock tokens
+
+  Background:
+    Given default settings
+    And an unlock token key is configured
+
+  Scenario: A valid token unlocks once and cannot be replayed
+    When a token is signed for "DESK-42" valid for 3600 seconds
+    And the token is presented on "DESK-42"
+    Then the token is accepted
+    When the token is presented on "DESK-42"
+    Then the token is rejected as Replayed
+
+  Scenario: A token for another machine is refused
+    When a token is signed for "DESK-42" valid for 3600 seconds
+    And the token is presented on "DESK-7"
+    Then the token is rejected as WrongMachine
+
+  Scenario: An expired token is refused
+    When a token is signed for "DESK-42" valid for -60 seconds
+    And the token is presented on "DESK-42"
+    Then the token is rejected as Expired
+
+  Scenario: A token signed by another key is refused
+    When a token is signed with a different key for "DESK-42"
+    And the token is presented on "DESK-42"
+    Then the token is rejected as BadSignature
+
+  Scenario: A token with an altered expiry is refused
+    When a token is signed for "DESK-42" valid for 3600 seconds
+    And the token expiry is pushed back
+    And the token is presented on "DESK-42"
+    Then the token is rejected as BadSignature
+
+  Scenario: A token file dropped into the watched directory is picked up once
+    When a token is signed for "DESK-42" valid for 3600 seconds
+    And the token is dropped into the token directory
+    And the token directory is read on "DESK-42"
+    Then the token is accepted
+    And the token directory is empty
+
+  Scenario: Nonces of expired tokens are forgotten
+    When a token is signed for "DESK-42" valid for 1 seconds
+    And the token is presented on "DESK-42"
+    And used nonces are pruned 2 seconds later
+    Then 0 used nonces remain
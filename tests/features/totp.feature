@@ -0,0 +1,46 @@
+Feature: Time-based one-time codes
+
+  Scenario: Codes match the RFC 6238 SHA-1 test vectors
+    Given the TOTP secret "12345678901234567890" with 8 digits and a skew of 0 steps
+    Then the code at 59 seconds is "94287082"
+    And the code at 1111111109 seconds is "07081804"
+
+  Scenario: A code from the previous step is accepted within the skew window
+    Given the TOTP secret "12345678901234567890" with 6 digits and a skew of 1 steps
+    When the code from 59 seconds is entered at 89 seconds
+    Then the unlock is accepted
+
+  Scenario: Codes outside the skew window are rejected
+    Given the TOTP secret "12345678901234567890" with 6 digits and a skew of 1 steps
+    When the code from 59 seconds is entered at 150 seconds
+    Then the unlock is rejected
+
+  Scenario: A code cannot be replayed in the same step
+    Given the TOTP secret "12345678901234567890" with 6 digits and a skew of 1 steps
+    When the code from 59 seconds is entered at 59 seconds
+    And the code from 59 seconds is entered at 60 seconds
+    Then the unlock is rejected
+
+  Scenario: A huge skew is capped
+    Given the TOTP secret "12345678901234567890" with 6 digits and a skew of 18446744073709551615 steps
+    When the code from 59 seconds is entered at 389 seconds
+    Then the unlock is rejected
+    When the code from 59 seconds is entered at 359 seconds
+    Then the unlock is accepted
+
+  Scenario: A used code stays spent on the next lock screen
+    Given default settings
+    And the only factor is the TOTP secret "12345678901234567890"
+    When the unlock chain is built
+    And the current TOTP code is submitted
+    Then the unlock is accepted
+    When the unlock chain records its use in the settings
+    And the unlock chain is built
+    And the current TOTP code is submitted
+    Then the unlock is rejected
+
+  Scenario: Enrollment adds a TOTP stage and returns an otpauth URI
+    Given default settings
+    When the user enrolls TOTP for "alice"
+    Then the enrollment URI starts with "otpauth://totp/DwmLock:alice?secret="
+    And the unlock chain has 2 stages